@@ -0,0 +1,371 @@
+use crate::backend::{
+    BackendFuture, BlueprintFactor, BlueprintMaterial, EveBackend, MarketPrice, MaterialPrice,
+    TypeIDFetch, TypeItem,
+};
+use crate::config::{CacheConfig, CacheKindConfig};
+use crate::constant::eve::server::Server;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What a cached answer was asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Search(TypeIDFetch<String>),
+    Candidates(TypeIDFetch<String>, usize),
+    Id(usize),
+    Price(Server, usize),
+    /// The materials as JSON, which has no `Hash`.
+    MaterialPrice(Server, String),
+    /// The factors with their floats as bits, which have no `Hash` either.
+    Material(usize, (u8, u8, u64, u64), bool),
+}
+
+macro_rules! answers {
+    ($($variant: ident($ty: ty)),+ $(,)?) => {
+        #[derive(Debug, Clone)]
+        enum Answer {
+            $($variant($ty)),+
+        }
+
+        $(
+            impl From<$ty> for Answer {
+                fn from(value: $ty) -> Self {
+                    Self::$variant(value)
+                }
+            }
+
+            impl TryFrom<Answer> for $ty {
+                type Error = ();
+
+                fn try_from(answer: Answer) -> Result<Self, ()> {
+                    match answer {
+                        Answer::$variant(value) => Ok(value),
+                        _ => Err(()),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+answers! {
+    Type(TypeItem),
+    Types(Vec<TypeItem>),
+    Price(MarketPrice),
+    MaterialPrice(MaterialPrice),
+    Material(BlueprintMaterial),
+}
+
+/// The counters of one kind of answers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Up to `capacity` answers, each kept for `ttl`. When it is full, expired answers are dropped
+/// first, then the oldest.
+#[derive(Debug)]
+struct TtlCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<Key, (Instant, Answer)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TtlCache {
+    fn new(config: &CacheKindConfig) -> Self {
+        Self {
+            ttl: config.ttl(),
+            capacity: config.capacity,
+            entries: Default::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &Key, now: Instant) -> Option<Answer> {
+        let mut entries = self.entries.lock().unwrap();
+        let answer = match entries.get(key) {
+            Some((stored, _)) if now.duration_since(*stored) >= self.ttl => {
+                entries.remove(key);
+                None
+            }
+            entry => entry.map(|(_, answer)| answer.clone()),
+        };
+        let counter = if answer.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        answer
+    }
+
+    fn insert(&self, key: Key, answer: Answer, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, (stored, _)| now.duration_since(*stored) < self.ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (stored, _))| *stored)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(key, (now, answer));
+    }
+
+    fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The answer kept for `key`, or the one of `fetch`, kept for next time. Errors are not.
+    fn cached<'a, T>(
+        &'a self,
+        key: Key,
+        fetch: impl FnOnce() -> BackendFuture<'a, T> + 'a,
+    ) -> BackendFuture<'a, T>
+    where
+        T: Clone + Into<Answer> + TryFrom<Answer> + 'a,
+    {
+        Box::pin(async move {
+            if let Some(Ok(value)) = self.get(&key, Instant::now()).map(T::try_from) {
+                return Ok(value);
+            }
+            let value = fetch().await?;
+            self.insert(key, value.clone().into(), Instant::now());
+            Ok(value)
+        })
+    }
+}
+
+/// The answers kept by a [`CachedBackend`], by kind as in [`CacheConfig`].
+#[derive(Debug)]
+pub struct BackendCache {
+    types: TtlCache,
+    prices: TtlCache,
+    materials: TtlCache,
+}
+
+impl BackendCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            types: TtlCache::new(&config.types),
+            prices: TtlCache::new(&config.prices),
+            materials: TtlCache::new(&config.materials),
+        }
+    }
+
+    /// The counters of each kind, by its readable name.
+    pub fn stats(&self) -> [(&'static str, CacheStats); 3] {
+        [
+            ("物品", self.types.stats()),
+            ("价格", self.prices.stats()),
+            ("蓝图材料", self.materials.stats()),
+        ]
+    }
+
+    /// Drop every answer, and return how many there were. The counters are kept.
+    pub fn flush(&self) -> usize {
+        self.types.clear() + self.prices.clear() + self.materials.clear()
+    }
+}
+
+/// `inner` with its answers kept in memory for a while, so that every command is cached
+/// without asking for it. Rendered images are always fetched again.
+#[derive(Debug)]
+pub struct CachedBackend<B> {
+    inner: B,
+    cache: BackendCache,
+}
+
+impl<B: EveBackend> CachedBackend<B> {
+    pub fn new(inner: B, config: &CacheConfig) -> Self {
+        Self {
+            inner,
+            cache: BackendCache::new(config),
+        }
+    }
+}
+
+fn owned(search: TypeIDFetch<&str>) -> TypeIDFetch<String> {
+    match search {
+        TypeIDFetch::Absolute(t) => TypeIDFetch::Absolute(t.to_owned()),
+        TypeIDFetch::Fuzzy(t) => TypeIDFetch::Fuzzy(t.to_owned()),
+        TypeIDFetch::Manual(p) => TypeIDFetch::Manual(p.to_owned()),
+    }
+}
+
+fn factor_key(factor: &BlueprintFactor) -> (u8, u8, u64, u64) {
+    (
+        factor.manu_material,
+        factor.manu_time,
+        factor.extra_material.to_bits(),
+        factor.extra_time.to_bits(),
+    )
+}
+
+impl<B: EveBackend> EveBackend for CachedBackend<B> {
+    fn search_type<'a>(&'a self, search: TypeIDFetch<&'a str>) -> BackendFuture<'a, TypeItem> {
+        self.cache
+            .types
+            .cached(Key::Search(owned(search)), move || {
+                self.inner.search_type(search)
+            })
+    }
+
+    fn search_types<'a>(
+        &'a self,
+        search: TypeIDFetch<&'a str>,
+        limit: usize,
+    ) -> BackendFuture<'a, Vec<TypeItem>> {
+        self.cache
+            .types
+            .cached(Key::Candidates(owned(search), limit), move || {
+                self.inner.search_types(search, limit)
+            })
+    }
+
+    fn type_by_id(&self, type_id: usize) -> BackendFuture<'_, TypeItem> {
+        self.cache
+            .types
+            .cached(Key::Id(type_id), move || self.inner.type_by_id(type_id))
+    }
+
+    fn jita_price(&self, server: Server, type_id: usize) -> BackendFuture<'_, MarketPrice> {
+        self.cache
+            .prices
+            .cached(Key::Price(server, type_id), move || {
+                self.inner.jita_price(server, type_id)
+            })
+    }
+
+    fn blueprint_material(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, BlueprintMaterial> {
+        let key = Key::Material(type_id, factor_key(&factor), expand);
+        self.cache.materials.cached(key, move || {
+            self.inner.blueprint_material(type_id, factor, expand)
+        })
+    }
+
+    fn blueprint_material_image(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, Vec<u8>> {
+        self.inner.blueprint_material_image(type_id, factor, expand)
+    }
+
+    fn blueprint_market<'a>(
+        &'a self,
+        server: Server,
+        material: &'a BlueprintMaterial,
+    ) -> BackendFuture<'a, MaterialPrice> {
+        let key = Key::MaterialPrice(server, material.0.to_string());
+        self.cache
+            .prices
+            .cached(key, move || self.inner.blueprint_market(server, material))
+    }
+
+    fn blueprint_market_image<'a>(
+        &'a self,
+        price: &'a MaterialPrice,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        self.inner.blueprint_market_image(price)
+    }
+
+    fn skill_image(&self, type_id: usize) -> BackendFuture<'_, Vec<u8>> {
+        self.inner.skill_image(type_id)
+    }
+
+    fn cache(&self) -> Option<&BackendCache> {
+        Some(&self.cache)
+    }
+}
+
+#[test]
+fn test_ttl_cache() {
+    let cache = TtlCache::new(&CacheKindConfig {
+        ttl: 60,
+        capacity: 2,
+    });
+    let start = Instant::now();
+    let price = |buy| {
+        Answer::Price(MarketPrice {
+            buy,
+            sell: buy,
+            medium: buy,
+        })
+    };
+    let key = |type_id| Key::Price(Server::Serenity, type_id);
+    cache.insert(key(1), price(1.0), start);
+    cache.insert(key(2), price(2.0), start + Duration::from_secs(1));
+    assert!(cache.get(&key(1), start).is_some());
+    // full, so the oldest goes
+    cache.insert(key(3), price(3.0), start + Duration::from_secs(2));
+    assert!(cache.get(&key(1), start).is_none());
+    assert!(cache.get(&key(2), start).is_some());
+    assert!(cache
+        .get(&key(3), start + Duration::from_secs(62))
+        .is_none());
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            entries: 1,
+            hits: 2,
+            misses: 2,
+        }
+    );
+
+    let backend = CachedBackend::new(crate::backend::MockBackend::default(), &Default::default());
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async {
+            for _ in 0..3 {
+                let price = backend.jita_price(Server::Serenity, 34).await.unwrap();
+                assert_eq!(price.sell, 4.89);
+            }
+            // errors are asked for again
+            assert!(backend.jita_price(Server::Serenity, 1).await.is_err());
+            assert!(backend.jita_price(Server::Serenity, 1).await.is_err());
+        });
+    let cache = backend.cache().unwrap();
+    assert_eq!(
+        cache.stats()[1].1,
+        CacheStats {
+            entries: 1,
+            hits: 2,
+            misses: 3,
+        }
+    );
+    assert_eq!(cache.flush(), 1);
+    assert_eq!(cache.stats()[1].1.entries, 0);
+}
@@ -0,0 +1,336 @@
+use crate::backend::{
+    BackendFuture, BlueprintFactor, BlueprintMaterial, EveBackend, MarketPrice, MaterialPrice,
+    TypeIDFetch, TypeItem,
+};
+use crate::config::{BackendConfig, Config};
+use crate::constant::eve::server::Server;
+use crate::error::{BotError, BotResult};
+use reqwest::{Method, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// The delay before the first retry, doubled for each one after it.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Classes of endpoints, each with its own limit of requests in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Search,
+    Price,
+    Render,
+}
+
+/// The local EVE service at the configured address.
+///
+/// It keeps one connection pool for the whole process, see [`crate::backend::init`].
+#[derive(Debug)]
+pub struct HttpBackend {
+    client: reqwest::Client,
+    config: BackendConfig,
+    /// How long a request may wait for its turn, the same as the request itself.
+    timeout: Duration,
+    search: Semaphore,
+    price: Semaphore,
+    render: Semaphore,
+}
+
+/// The delay before retry number `attempt`, with up to as much again added at random so that
+/// waiting requests do not all come back at once.
+fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_DELAY * 2u32.saturating_pow(attempt);
+    delay + delay.mul_f64(rand::random::<f64>())
+}
+
+/// A hung backend is not retried, it would only hang for longer.
+fn retryable(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.status().is_some_and(|s| s.is_server_error())
+}
+
+impl HttpBackend {
+    pub fn new(config: &Config) -> reqwest::Result<Self> {
+        let timeout = &config.timeout;
+        let concurrency = &config.backend.concurrency;
+        Ok(Self {
+            client: reqwest::ClientBuilder::new()
+                .connect_timeout(timeout.connect())
+                .timeout(timeout.backend())
+                .build()?,
+            config: config.backend.clone(),
+            timeout: timeout.backend(),
+            search: Semaphore::new(concurrency.search),
+            price: Semaphore::new(concurrency.price),
+            render: Semaphore::new(concurrency.render),
+        })
+    }
+
+    fn get(&self, path: impl AsRef<str>) -> BotResult<Request> {
+        self.get_with(path, &[])
+    }
+
+    /// A GET with `query` encoded into the URL.
+    fn get_with(&self, path: impl AsRef<str>, query: &[(&str, &str)]) -> BotResult<Request> {
+        Ok(self
+            .client
+            .get(self.config.url(path))
+            .query(query)
+            .build()?)
+    }
+
+    fn post(
+        &self,
+        path: impl AsRef<str>,
+        query: &[(&str, &str)],
+        body: &impl Serialize,
+    ) -> BotResult<Request> {
+        Ok(self
+            .client
+            .post(self.config.url(path))
+            .query(query)
+            .json(body)
+            .build()?)
+    }
+
+    /// Wait for a free slot of `endpoint`. Waiting too long means the backend is busy as well.
+    async fn acquire(&self, endpoint: Endpoint) -> BotResult<SemaphorePermit<'_>> {
+        let semaphore = match endpoint {
+            Endpoint::Search => &self.search,
+            Endpoint::Price => &self.price,
+            Endpoint::Render => &self.render,
+        };
+        match tokio::time::timeout(self.timeout, semaphore.acquire()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => Err(BotError::Timeout),
+        }
+    }
+
+    /// A GET is tried again after failing to connect or a 5xx answer, up to the configured
+    /// number of retries.
+    async fn send(&self, request: Request) -> BotResult<Response> {
+        let retries = if request.method() == Method::GET {
+            self.config.retries
+        } else {
+            0
+        };
+        for attempt in 0..retries {
+            // always `Some` for a GET, which has no body
+            let Some(retry) = request.try_clone() else {
+                break;
+            };
+            match self
+                .client
+                .execute(retry)
+                .await
+                .and_then(Response::error_for_status)
+            {
+                Err(err) if retryable(&err) => tokio::time::sleep(backoff(attempt)).await,
+                result => return Ok(result?),
+            }
+        }
+        Ok(self.client.execute(request).await?.error_for_status()?)
+    }
+
+    async fn json<T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        request: BotResult<Request>,
+    ) -> BotResult<T> {
+        let _permit = self.acquire(endpoint).await?;
+        Ok(self.send(request?).await?.json().await?)
+    }
+
+    async fn image(&self, request: BotResult<Request>) -> BotResult<Vec<u8>> {
+        let _permit = self.acquire(Endpoint::Render).await?;
+        Ok(self.send(request?).await?.bytes().await?.to_vec())
+    }
+}
+
+#[test]
+fn test_http_backend() {
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let handler = {
+        let hits = hits.clone();
+        move || {
+            let hits = hits.clone();
+            async move {
+                // the backend fails twice before answering
+                match hits.fetch_add(1, Ordering::SeqCst) % 3 {
+                    2 => HttpResponse::Ok().json(TypeItem {
+                        type_id: 34,
+                        type_name: "三钛合金".into(),
+                        published: true,
+                    }),
+                    _ => HttpResponse::ServiceUnavailable().finish(),
+                }
+            }
+        }
+    };
+    // answers with the name it was asked for
+    let echo = |query: web::Query<std::collections::HashMap<String, String>>| async move {
+        HttpResponse::Ok().json(TypeItem {
+            type_id: 0,
+            type_name: query.get("name").cloned().unwrap_or_default(),
+            published: false,
+        })
+    };
+    let slow = || async {
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        HttpResponse::Ok().finish()
+    };
+    actix_web::rt::System::new().block_on(async move {
+        let server = HttpServer::new(move || {
+            App::new()
+                .route("/types/34/", web::get().to(handler.clone()))
+                .route("/blueprint/34/plain/", web::post().to(handler.clone()))
+                .route("/types/search/fuzzy/", web::get().to(echo))
+                .route("/skill/prereq/34/image/", web::get().to(slow))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        actix_web::rt::spawn(server.run());
+
+        let mut config = Config::default();
+        config.backend.host = "127.0.0.1".into();
+        config.backend.port = port;
+        config.timeout.backend = 1;
+        let backend = HttpBackend::new(&config).unwrap();
+        assert_eq!(backend.type_by_id(34).await.unwrap().type_id, 34);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        // a POST is never sent twice
+        let factor = BlueprintFactor::new(0, 0, 0.0, 0.0);
+        assert!(matches!(
+            backend.blueprint_material(34, factor, false).await,
+            Err(BotError::Backend { code: Some(code), .. }) if code.as_u16() == 503
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+        let name = "R&D #1 + 50% off";
+        let found = backend.search_type(TypeIDFetch::Fuzzy(name)).await.unwrap();
+        assert_eq!(found.type_name, name);
+        // no list endpoint, so the single best match is the only candidate
+        let found = backend
+            .search_types(TypeIDFetch::Fuzzy(name), 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].type_name, name);
+        assert!(matches!(
+            backend.skill_image(34).await,
+            Err(BotError::Timeout)
+        ));
+    });
+}
+
+#[inline]
+fn blueprint_mode(expand: bool) -> &'static str {
+    if expand {
+        "recursive"
+    } else {
+        "plain"
+    }
+}
+
+impl EveBackend for HttpBackend {
+    fn search_type<'a>(&'a self, search: TypeIDFetch<&'a str>) -> BackendFuture<'a, TypeItem> {
+        let request = match search {
+            TypeIDFetch::Absolute(t) => self.get_with("/types/search/absolute/", &[("name", t)]),
+            TypeIDFetch::Fuzzy(t) => self.get_with("/types/search/fuzzy/", &[("name", t)]),
+            TypeIDFetch::Manual(p) => self.get_with("/types/search/manual/", &[("pattern", p)]),
+        };
+        Box::pin(self.json(Endpoint::Search, request))
+    }
+
+    /// Answered by `GET /types/search/{fuzzy,manual}/list/?name|pattern=…&limit=…` as a JSON
+    /// list of types, the best match first, and `[]` if nothing matches.
+    ///
+    /// A backend without these endpoints answers `404`, and is asked for its single best match
+    /// with [`EveBackend::search_type`] instead, so there is never a choice to make.
+    fn search_types<'a>(
+        &'a self,
+        search: TypeIDFetch<&'a str>,
+        limit: usize,
+    ) -> BackendFuture<'a, Vec<TypeItem>> {
+        let limit = limit.to_string();
+        let request = match search {
+            TypeIDFetch::Absolute(_) => {
+                return Box::pin(async move { Ok(vec![self.search_type(search).await?]) });
+            }
+            TypeIDFetch::Fuzzy(t) => self.get_with(
+                "/types/search/fuzzy/list/",
+                &[("name", t), ("limit", &limit)],
+            ),
+            TypeIDFetch::Manual(p) => self.get_with(
+                "/types/search/manual/list/",
+                &[("pattern", p), ("limit", &limit)],
+            ),
+        };
+        Box::pin(async move {
+            match self.json(Endpoint::Search, request).await {
+                Err(BotError::Backend {
+                    code: Some(StatusCode::NOT_FOUND),
+                    ..
+                }) => Ok(vec![self.search_type(search).await?]),
+                result => result,
+            }
+        })
+    }
+
+    fn type_by_id(&self, type_id: usize) -> BackendFuture<'_, TypeItem> {
+        Box::pin(self.json(Endpoint::Search, self.get(format!("/types/{}/", type_id))))
+    }
+
+    fn jita_price(&self, server: Server, type_id: usize) -> BackendFuture<'_, MarketPrice> {
+        let path = format!("/market/jita/type/{}/{}/", server.as_api_like(), type_id);
+        Box::pin(self.json(Endpoint::Price, self.get(path)))
+    }
+
+    fn blueprint_material(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, BlueprintMaterial> {
+        let path = format!("/blueprint/{}/{}/", type_id, blueprint_mode(expand));
+        Box::pin(self.json(Endpoint::Price, self.post(path, &[], &factor)))
+    }
+
+    fn blueprint_material_image(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, Vec<u8>> {
+        let path = format!("/blueprint/{}/{}/image/", type_id, blueprint_mode(expand));
+        Box::pin(self.image(self.post(path, &[], &factor)))
+    }
+
+    fn blueprint_market<'a>(
+        &'a self,
+        server: Server,
+        material: &'a BlueprintMaterial,
+    ) -> BackendFuture<'a, MaterialPrice> {
+        let request = self.post(
+            "/blueprint/market/",
+            &[("s", server.as_api_like())],
+            material,
+        );
+        Box::pin(self.json(Endpoint::Price, request))
+    }
+
+    fn blueprint_market_image<'a>(
+        &'a self,
+        price: &'a MaterialPrice,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.image(self.post("/blueprint/market/image/", &[], price)))
+    }
+
+    fn skill_image(&self, type_id: usize) -> BackendFuture<'_, Vec<u8>> {
+        Box::pin(self.image(self.get(format!("/skill/prereq/{}/image/", type_id))))
+    }
+}
@@ -0,0 +1,188 @@
+use crate::backend::{
+    BackendFuture, BlueprintFactor, BlueprintMaterial, EveBackend, MarketPrice, MaterialPrice,
+    TypeIDFetch, TypeItem,
+};
+use crate::constant::eve::server::Server;
+use crate::error::{BotError, BotResult};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// An in-process backend answering from `fixture.json`, for tests.
+///
+/// Images are the UTF-8 description of what would be drawn, so tests can tell them apart.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockBackend {
+    types: Vec<TypeItem>,
+    /// Keyed by `Server::as_api_like`, then by type id.
+    prices: HashMap<String, HashMap<usize, MarketPrice>>,
+    /// The plain materials of each blueprint.
+    materials: HashMap<usize, serde_json::Value>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        serde_json::from_str(include_str!("fixture.json")).expect("invalid backend fixture")
+    }
+}
+
+fn not_found(what: impl std::fmt::Display) -> BotError {
+    BotError::Backend {
+        code: Some(StatusCode::NOT_FOUND),
+        source: format!("{} not found", what),
+    }
+}
+
+fn ready<'a, T: 'a>(result: BotResult<T>) -> BackendFuture<'a, T> {
+    Box::pin(std::future::ready(result))
+}
+
+/// SQL `LIKE`, without escapes.
+fn like(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|i| like(rest, &text[i..])),
+        Some(('_', rest)) => !text.is_empty() && like(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && like(rest, &text[1..]),
+    }
+}
+
+impl MockBackend {
+    /// Every type matching `search`, the best match first.
+    fn find_types(&self, search: TypeIDFetch<&str>) -> Vec<&TypeItem> {
+        let types = self.types.iter();
+        let mut found: Vec<_> = match search {
+            TypeIDFetch::Absolute(name) => types.filter(|t| t.type_name == name).collect(),
+            TypeIDFetch::Fuzzy(name) => types.filter(|t| t.type_name.contains(name)).collect(),
+            TypeIDFetch::Manual(pattern) => {
+                let pattern = pattern.chars().collect::<Vec<_>>();
+                types
+                    .filter(|t| like(&pattern, &t.type_name.chars().collect::<Vec<_>>()))
+                    .collect()
+            }
+        };
+        // the closest match is the shortest name
+        found.sort_by_key(|t| (!t.published, t.type_name.chars().count(), t.type_id));
+        found
+    }
+
+    fn find_id(&self, type_id: usize) -> BotResult<&TypeItem> {
+        self.types
+            .iter()
+            .find(|t| t.type_id == type_id)
+            .ok_or_else(|| not_found(format!("type {}", type_id)))
+    }
+
+    fn materials(&self, type_id: usize) -> BotResult<&serde_json::Value> {
+        self.materials
+            .get(&type_id)
+            .ok_or_else(|| not_found(format!("blueprint {}", type_id)))
+    }
+}
+
+impl EveBackend for MockBackend {
+    fn search_type<'a>(&'a self, search: TypeIDFetch<&'a str>) -> BackendFuture<'a, TypeItem> {
+        ready(
+            self.find_types(search)
+                .first()
+                .copied()
+                .cloned()
+                .ok_or_else(|| not_found(format!("{:?}", search))),
+        )
+    }
+
+    fn search_types<'a>(
+        &'a self,
+        search: TypeIDFetch<&'a str>,
+        limit: usize,
+    ) -> BackendFuture<'a, Vec<TypeItem>> {
+        let found = self.find_types(search);
+        ready(Ok(found.into_iter().take(limit).cloned().collect()))
+    }
+
+    fn type_by_id(&self, type_id: usize) -> BackendFuture<'_, TypeItem> {
+        ready(self.find_id(type_id).cloned())
+    }
+
+    fn jita_price(&self, server: Server, type_id: usize) -> BackendFuture<'_, MarketPrice> {
+        ready(
+            self.prices
+                .get(server.as_api_like())
+                .and_then(|prices| prices.get(&type_id))
+                .copied()
+                .ok_or_else(|| not_found(format!("price of {}", type_id))),
+        )
+    }
+
+    fn blueprint_material(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, BlueprintMaterial> {
+        ready(self.materials(type_id).map(|materials| {
+            BlueprintMaterial(json! {{
+                "blueprint": type_id,
+                "factor": factor,
+                "expand": expand,
+                "materials": materials,
+            }})
+        }))
+    }
+
+    fn blueprint_material_image(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, Vec<u8>> {
+        ready(
+            self.materials(type_id).map(|_| {
+                format!("blueprint {} {:?} expand={}", type_id, factor, expand).into_bytes()
+            }),
+        )
+    }
+
+    fn blueprint_market<'a>(
+        &'a self,
+        server: Server,
+        material: &'a BlueprintMaterial,
+    ) -> BackendFuture<'a, MaterialPrice> {
+        let prices = self.prices.get(server.as_api_like());
+        let items = material.0["materials"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|item| {
+                let price = item["type_id"]
+                    .as_u64()
+                    .and_then(|id| prices?.get(&(id as usize)))
+                    .map(|p| p.sell);
+                json! {{
+                    "type_id": item["type_id"],
+                    "quantity": item["quantity"],
+                    "price": price,
+                }}
+            })
+            .collect::<Vec<_>>();
+        ready(Ok(MaterialPrice(json! {{
+            "server": server.as_api_like(),
+            "items": items,
+        }})))
+    }
+
+    fn blueprint_market_image<'a>(
+        &'a self,
+        price: &'a MaterialPrice,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        ready(Ok(format!("market {}", price.0).into_bytes()))
+    }
+
+    fn skill_image(&self, type_id: usize) -> BackendFuture<'_, Vec<u8>> {
+        ready(
+            self.find_id(type_id)
+                .map(|t| format!("skills of {}", t.type_name).into_bytes()),
+        )
+    }
+}
@@ -0,0 +1,239 @@
+//! The local EVE service, which answers everything about types, markets and blueprints.
+//!
+//! Commands only see it as a `&dyn EveBackend`, so tests can swap in a `MockBackend`. The one
+//! of the process keeps its answers for a while, see [`CachedBackend`].
+
+mod cache;
+mod http;
+#[cfg(test)]
+mod mock;
+
+pub use cache::*;
+pub use http::*;
+#[cfg(test)]
+pub use mock::*;
+
+use crate::config::config;
+use crate::constant::eve::server::Server;
+use crate::error::{BotError, BotResult};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+/// The longest pattern of a manual search, in characters.
+pub const MAX_PATTERN_LENGTH: usize = 32;
+
+/// How many candidates of a search the user can choose from.
+pub const MAX_CANDIDATES: usize = 5;
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = BotResult<T>> + 'a>>;
+
+pub trait EveBackend {
+    fn search_type<'a>(&'a self, search: TypeIDFetch<&'a str>) -> BackendFuture<'a, TypeItem>;
+
+    /// Up to `limit` types matching `search`, the best match first. Empty if none match.
+    ///
+    /// Absolute searches have a single answer. Backends unable to list candidates may answer
+    /// with their best match only, see [`HttpBackend`].
+    fn search_types<'a>(
+        &'a self,
+        search: TypeIDFetch<&'a str>,
+        limit: usize,
+    ) -> BackendFuture<'a, Vec<TypeItem>>;
+
+    fn type_by_id(&self, type_id: usize) -> BackendFuture<'_, TypeItem>;
+
+    fn jita_price(&self, server: Server, type_id: usize) -> BackendFuture<'_, MarketPrice>;
+
+    /// The materials to build the blueprint `type_id`, down to the raw materials if `expand`.
+    fn blueprint_material(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, BlueprintMaterial>;
+
+    /// [`EveBackend::blueprint_material`], rendered as a PNG image.
+    fn blueprint_material_image(
+        &self,
+        type_id: usize,
+        factor: BlueprintFactor,
+        expand: bool,
+    ) -> BackendFuture<'_, Vec<u8>>;
+
+    fn blueprint_market<'a>(
+        &'a self,
+        server: Server,
+        material: &'a BlueprintMaterial,
+    ) -> BackendFuture<'a, MaterialPrice>;
+
+    /// [`EveBackend::blueprint_market`], rendered as a PNG image.
+    fn blueprint_market_image<'a>(&'a self, price: &'a MaterialPrice)
+        -> BackendFuture<'a, Vec<u8>>;
+
+    /// The skills required to use `type_id`, rendered as a PNG image.
+    fn skill_image(&self, type_id: usize) -> BackendFuture<'_, Vec<u8>>;
+
+    /// The answers kept in memory, if this backend keeps any.
+    fn cache(&self) -> Option<&BackendCache> {
+        None
+    }
+}
+
+static BACKEND: OnceLock<CachedBackend<HttpBackend>> = OnceLock::new();
+
+/// Install the backend shared by the whole process. Only the first call has any effect.
+pub fn init(backend: CachedBackend<HttpBackend>) -> &'static CachedBackend<HttpBackend> {
+    let _ = BACKEND.set(backend);
+    BACKEND.get().unwrap()
+}
+
+/// The backend installed by [`init`], or one built from [`config`] if there is none.
+pub fn backend() -> &'static dyn EveBackend {
+    BACKEND.get_or_init(|| {
+        let http = HttpBackend::new(config()).expect("failed to build the HTTP client");
+        CachedBackend::new(http, &config().cache)
+    })
+}
+
+/// The type `search` is meant to find: the only match, or the one named exactly like the search.
+///
+/// Otherwise the candidates are returned as a [`BotError::Ambiguous`], for the user to choose.
+pub async fn pick_type(backend: &dyn EveBackend, search: TypeIDFetch<&str>) -> BotResult<TypeItem> {
+    let name = match search {
+        TypeIDFetch::Absolute(_) => return backend.search_type(search).await,
+        TypeIDFetch::Fuzzy(name) | TypeIDFetch::Manual(name) => name,
+    };
+    let mut candidates = backend.search_types(search, MAX_CANDIDATES).await?;
+    if let Some(i) = candidates.iter().position(|t| t.type_name == name) {
+        return Ok(candidates.swap_remove(i));
+    }
+    match candidates.len() {
+        0 => Err(BotError::Backend {
+            code: Some(reqwest::StatusCode::NOT_FOUND),
+            source: format!("没有找到物品：{}", name),
+        }),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(BotError::Ambiguous { candidates }),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub struct TypeItem {
+    pub type_id: usize,
+    pub type_name: String,
+    #[serde(default)]
+    pub published: bool,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeIDFetch<T: AsRef<str>> {
+    Absolute(T),
+    Fuzzy(T),
+    /// A SQL `LIKE` pattern, see [`TypeIDFetch::manual`].
+    Manual(T),
+}
+
+impl<T: AsRef<str>> TypeIDFetch<T> {
+    pub fn as_deref(&self) -> TypeIDFetch<&str> {
+        match self {
+            Self::Absolute(t) => TypeIDFetch::Absolute(t.as_ref()),
+            Self::Fuzzy(t) => TypeIDFetch::Fuzzy(t.as_ref()),
+            Self::Manual(p) => TypeIDFetch::Manual(p.as_ref()),
+        }
+    }
+
+    /// A manual search for `pattern`, if it is safe to forward: the only wildcards are `%` and
+    /// `_`, it is at most [`MAX_PATTERN_LENGTH`] characters long, and not only wildcards.
+    pub fn manual(pattern: T) -> BotResult<Self> {
+        let p = pattern.as_ref();
+        let error = |note: String| BotError::Syntax {
+            found: Some(p.to_owned()),
+            expected: Some("由文字和通配符 % _ 组成的模式".to_string()),
+            note: Some(note),
+            span: None,
+        };
+        if p.chars().count() > MAX_PATTERN_LENGTH {
+            return Err(error(format!("模式最长 {} 个字符", MAX_PATTERN_LENGTH)));
+        }
+        // escapes and character classes of some SQL dialects
+        if p.contains(['[', ']', '\\']) {
+            return Err(error("只支持 % 和 _ 两种通配符".to_string()));
+        }
+        if p.chars().all(|c| c == '%' || c == '_' || c.is_whitespace()) {
+            return Err(error("模式不能为空或只有通配符".to_string()));
+        }
+        Ok(Self::Manual(pattern))
+    }
+
+    /// Parse the `pattern` parameter of a command, to search for the value given to it.
+    pub fn type_from(val: impl AsRef<str>) -> Box<dyn FnOnce(T) -> BotResult<Self>> {
+        match val.as_ref().to_ascii_lowercase().as_str() {
+            "absolute" | "a" | "abs" => Box::new(|v| Ok(Self::Absolute(v))),
+            "fuzzy" | "f" | "fuz" | "fuzz" => Box::new(|v| Ok(Self::Fuzzy(v))),
+            "manual" | "m" | "man" => Box::new(|v| Self::manual(v)),
+            _ => {
+                let val = val.as_ref().to_owned();
+                Box::new(move |_| {
+                    Err(BotError::Syntax {
+                        found: Some(val),
+                        expected: Some("choices: [a]bsolute, [f]uzzy, [m]anual.".to_string()),
+                        note: Some("use a valid fetch pattern type.".to_string()),
+                        span: None,
+                    })
+                })
+            }
+        }
+    }
+}
+
+#[test]
+fn test_manual_pattern() {
+    let manual = |p: &str| TypeIDFetch::type_from("m")(p.to_string());
+    assert_eq!(
+        manual("三%合_").unwrap(),
+        TypeIDFetch::Manual("三%合_".to_string())
+    );
+    for pattern in ["", "%", "%_% ", "[a-z]%", "a\\%", &"三".repeat(33)] {
+        assert!(manual(pattern).is_err(), "{:?}", pattern);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MarketPrice {
+    pub buy: f64,
+    pub sell: f64,
+    pub medium: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlueprintFactor {
+    manu_material: u8,
+    manu_time: u8,
+    extra_material: f64,
+    extra_time: f64,
+}
+
+impl BlueprintFactor {
+    #[inline]
+    pub fn new(manu_material: u8, manu_time: u8, extra_material: f64, extra_time: f64) -> Self {
+        Self {
+            manu_time,
+            manu_material,
+            extra_material,
+            extra_time,
+        }
+    }
+}
+
+/// The materials of a blueprint. Only the backend reads it, so it is kept as is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlueprintMaterial(pub serde_json::Value);
+
+/// The prices of a [`BlueprintMaterial`]. Only the backend reads it, so it is kept as is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MaterialPrice(pub serde_json::Value);
@@ -0,0 +1,71 @@
+use crate::backend::TypeIDFetch;
+use crate::config::{Account, GroupConfig};
+use crate::error::{BotError, BotResult};
+use crate::server::{ParamItem, Token};
+use std::future::Future;
+
+tokio::task_local! {
+    static ACCOUNT: Account;
+    static GROUP: Option<GroupConfig>;
+}
+
+/// Run `f` on behalf of `account`, so commands parsed in it use its [`param_default`]s.
+pub async fn with_account<F: Future>(account: Account, f: F) -> F::Output {
+    ACCOUNT.scope(account, f).await
+}
+
+/// The value the current account gives to the parameter `name` when it is left out.
+///
+/// Used by `create_syntax` before the default of the syntax itself.
+pub fn param_default(name: &str) -> Option<Token> {
+    ACCOUNT
+        .try_with(|account| account.defaults.get(name).cloned())
+        .ok()
+        .flatten()
+        .map(|value| ParamItem::Text(value).into())
+}
+
+/// Run `f` for a message from `group`, `None` in private chats or groups without settings.
+pub async fn with_group<F: Future>(group: Option<GroupConfig>, f: F) -> F::Output {
+    GROUP.scope(group, f).await
+}
+
+/// Whether the current group allows searching with a manual pattern. Always true outside
+/// [`with_group`].
+pub fn manual_pattern_allowed() -> bool {
+    GROUP
+        .try_with(|group| group.as_ref().is_none_or(|g| g.manual_pattern))
+        .unwrap_or(true)
+}
+
+/// `search`, unless the current group does not allow it, see [`manual_pattern_allowed`].
+pub fn allow_search<T: AsRef<str>>(search: TypeIDFetch<T>) -> BotResult<TypeIDFetch<T>> {
+    match search {
+        TypeIDFetch::Manual(p) if !manual_pattern_allowed() => Err(BotError::Syntax {
+            found: Some(p.as_ref().to_owned()),
+            expected: Some("choices: [a]bsolute, [f]uzzy.".to_string()),
+            note: Some("本群不允许手动搜索".to_string()),
+            span: None,
+        }),
+        search => Ok(search),
+    }
+}
+
+#[test]
+fn test_allow_search() {
+    let in_group = |manual_pattern| {
+        let group = GroupConfig {
+            manual_pattern,
+            ..Default::default()
+        };
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(with_group(Some(group), async {
+                allow_search(TypeIDFetch::Manual("三%")).is_ok()
+            }))
+    };
+    assert!(in_group(true));
+    assert!(!in_group(false));
+    assert!(allow_search(TypeIDFetch::Manual("三%")).is_ok());
+}
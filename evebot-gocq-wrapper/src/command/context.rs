@@ -0,0 +1,131 @@
+use crate::backend::EveBackend;
+use crate::command::{CommandLimit, Pending};
+use crate::error::BotGroupResult;
+use crate::server::{MessageChain, Token};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// How many answers can be followed up on.
+const CONTEXT_CAPACITY: usize = 256;
+
+/// A parsed command kept so that a quote-reply to its answer can re-run it with changes, or so
+/// that it can run once the user chose a type, see [`crate::command::Pending`].
+///
+/// Implemented by `create_syntax` for every command. Only those with a parameter marked
+/// `follow_up` can be followed up on.
+pub trait FollowUp {
+    /// A copy of the command with the follow-up parameters in `param` applied, or `None` if
+    /// `param` does not start with one, so the message was not meant as a follow-up.
+    fn follow_up(&self, param: Vec<Token>) -> Option<BotGroupResult<Context>>;
+
+    fn run<'a>(
+        &'a self,
+        backend: &'a dyn EveBackend,
+    ) -> Pin<Box<dyn Future<Output = BotGroupResult<MessageChain>> + 'a>>;
+
+    /// The rate limit of the command, see [`CommandLimit::wrap`].
+    fn limit(&self) -> Option<CommandLimit> {
+        None
+    }
+}
+
+pub type Context = Arc<dyn FollowUp + Send + Sync>;
+
+/// The answer of a command, and its context if it can be followed up on.
+pub struct CommandOutput {
+    pub message: MessageChain,
+    pub context: Option<Context>,
+    /// Set if the answer asks the sender to choose, see [`Pending::ask`].
+    pub pending: Option<Pending>,
+}
+
+impl std::fmt::Debug for CommandOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommandOutput")
+            .field("message", &self.message)
+            .field("context", &self.context.is_some())
+            .field("pending", &self.pending.is_some())
+            .finish()
+    }
+}
+
+impl From<MessageChain> for CommandOutput {
+    #[inline]
+    fn from(message: MessageChain) -> Self {
+        Self {
+            message,
+            context: None,
+            pending: None,
+        }
+    }
+}
+
+/// The contexts of recent answers, keyed by the account and message id, as message ids are only
+/// unique to the account that saw them. The oldest is dropped when full.
+#[derive(Default)]
+pub struct ContextStore {
+    contexts: HashMap<(u64, i64), Context>,
+    order: VecDeque<(u64, i64)>,
+}
+
+impl ContextStore {
+    pub fn insert(&mut self, self_id: u64, message_id: i64, context: Context) {
+        let key = (self_id, message_id);
+        if self.contexts.insert(key, context).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CONTEXT_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.contexts.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, self_id: u64, message_id: i64) -> Option<Context> {
+        self.contexts.get(&(self_id, message_id)).cloned()
+    }
+}
+
+pub fn context_store() -> &'static Mutex<ContextStore> {
+    static STORE: OnceLock<Mutex<ContextStore>> = OnceLock::new();
+    STORE.get_or_init(Default::default)
+}
+
+/// Remember `context` as the context of the message `message_id`, as seen by `self_id`.
+pub fn save_context(self_id: u64, message_id: i64, context: Context) {
+    if let Ok(mut store) = context_store().lock() {
+        store.insert(self_id, message_id, context);
+    }
+}
+
+pub fn load_context(self_id: u64, message_id: i64) -> Option<Context> {
+    context_store().lock().ok()?.get(self_id, message_id)
+}
+
+#[test]
+fn test_context_store() {
+    struct Dummy;
+    impl FollowUp for Dummy {
+        fn follow_up(&self, _: Vec<Token>) -> Option<BotGroupResult<Context>> {
+            None
+        }
+        fn run<'a>(
+            &'a self,
+            _: &'a dyn EveBackend,
+        ) -> Pin<Box<dyn Future<Output = BotGroupResult<MessageChain>> + 'a>> {
+            Box::pin(async { Ok(MessageChain::new()) })
+        }
+    }
+    let mut store = ContextStore::default();
+    for id in 0..CONTEXT_CAPACITY as i64 + 2 {
+        store.insert(1, id, Arc::new(Dummy));
+    }
+    store.insert(1, 5, Arc::new(Dummy));
+    assert!(store.get(1, 0).is_none());
+    assert!(store.get(1, 1).is_none());
+    assert!(store.get(1, 2).is_some());
+    assert!(store.get(2, 2).is_none());
+    assert_eq!(store.order.len(), CONTEXT_CAPACITY);
+}
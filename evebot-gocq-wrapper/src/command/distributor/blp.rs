@@ -1,9 +1,8 @@
-use crate::constant::eve::server::Server;
-use crate::error::{BotError, BotErrorGroup, BotGroupResult};
-use crate::metadata::{IMAGE_DIRECTORY, LOCAL_EVE_SERVICE_PORT};
-use crate::utils::fetch::{download_image, random_filename};
-use crate::{build_single_image, fetch_type};
-use serde::{Deserialize, Serialize};
+use crate::backend::{BlueprintFactor, EveBackend};
+use crate::error::{BotErrorGroup, BotGroupResult};
+use crate::fetch_type;
+use crate::server::MessageChain;
+use crate::utils::fetch::save_image;
 
 #[test]
 fn test_blp_mat_image() {
@@ -14,33 +13,136 @@ fn test_blp_mat_image() {
         .build()
         .unwrap();
     println!("{}\n\n", BlpMaterial::SYNTAX);
-    runtime.block_on(async {
-        let res = BlpMaterial::parse(
-            [
-                ParamItem::Text("帕拉丁级蓝图".into()),
-                ParamItem::Text("pattern".into()),
-                ParamItem::Text("f".into()),
-                ParamItem::Text("mt".into()),
-                ParamItem::Text("3".into()),
-                ParamItem::Text("em".into()),
-                ParamItem::Text("10".into()),
-                ParamItem::Text("exp".into()),
-                ParamItem::Text("true".into()),
-            ]
+    let res = BlpMaterial::parse(
+        [
+            ParamItem::Text("帕拉丁级蓝图".into()),
+            ParamItem::Text("pattern".into()),
+            ParamItem::Text("f".into()),
+            ParamItem::Text("mt".into()),
+            ParamItem::Text("3".into()),
+            ParamItem::Text("em".into()),
+            ParamItem::Text("10".into()),
+            ParamItem::Text("exp".into()),
+            ParamItem::Text("true".into()),
+        ]
+        .into_iter()
+        .peekable(),
+    )
+    .unwrap();
+    let image = runtime
+        .block_on(res.get_content(&crate::backend::MockBackend::default()))
+        .unwrap();
+    assert_eq!(
+        crate::utils::fetch::take_image(&image),
+        format!(
+            "blueprint 28660 {:?} expand=true",
+            BlueprintFactor::new(0, 3, 10.0, 0.0)
+        )
+    );
+}
+
+#[test]
+fn test_blp_expand_flag() {
+    use crate::command::BotService;
+    use crate::server::ParamItem;
+    let parse = |items: &[&str]| {
+        BlpMaterial::parse(
+            items
+                .iter()
+                .map(|s| ParamItem::Text(s.to_string()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .peekable(),
+        )
+        .unwrap()
+    };
+    assert!(!parse(&["帕拉丁级蓝图"]).expand);
+    assert!(parse(&["帕拉丁级蓝图", "e", "mm", "10"]).expand);
+    assert!(!parse(&["帕拉丁级蓝图", "no-e"]).expand);
+    assert!(parse(&["帕拉丁级蓝图", "exp", "true", "mm", "10"]).expand);
+    assert!(!parse(&["帕拉丁级蓝图", "expand", "false"]).expand);
+    assert!(BlpMaterial::SYNTAX_TEXT.contains("Turn off: no-expand"));
+    // `no-expand true` contradicts itself
+    let err = BlpMaterial::parse(
+        ["帕拉丁级蓝图", "no-expand", "true"]
+            .map(|s| ParamItem::Text(s.into()))
             .into_iter()
             .peekable(),
-        );
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(image) => println!("{:#}", image),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.as_ref().as_slice(),
+        [crate::error::BotError::Syntax { found: Some(found), .. }] if found == "no-expand true"
+    ));
+}
+
+#[test]
+fn test_blp_factor_range() {
+    use crate::error::BotError;
+    use crate::server::ParamItem;
+    let err = BlpMaterial::parse(
+        ["帕拉丁级蓝图", "mm", "11", "em", "-1", "mt", "10"]
+            .map(|s| ParamItem::Text(s.into()))
+            .into_iter()
+            .peekable(),
+    )
+    .unwrap_err();
+    let notes = err
+        .as_ref()
+        .iter()
+        .map(|e| match e {
+            BotError::Syntax { found, note, .. } => (found.clone().unwrap(), note.clone().unwrap()),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        vec![
+            ("11".to_string(), "取值超出范围 [0, 10]".to_string()),
+            ("-1".to_string(), "取值超出范围 [0, ...)".to_string()),
+        ]
+    );
 }
 
-#[evebot_proc_macro::create_syntax("evebot-gocq-wrapper/syntax/command/blp_mat.json")]
+#[test]
+fn test_blp_follow_up() {
+    use crate::command::BotService;
+    use crate::constant::eve::server::Server;
+    use crate::server::ParamItem;
+    let tokens = |items: &[&str]| {
+        items
+            .iter()
+            .map(|s| ParamItem::Text(s.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .peekable()
+    };
+    let origin =
+        BlpMaterialPrice::parse(tokens(&["帕拉丁级蓝图", "pattern", "f", "mm", "5"])).unwrap();
+    let res = origin.apply_follow_up(tokens(&["tq"])).unwrap().unwrap();
+    assert_eq!(res.server, Server::Tranquility);
+    assert_eq!(res.manu_mat_level, 5);
+    assert_eq!(res.type_name, "帕拉丁级蓝图");
+    let res = origin
+        .apply_follow_up(tokens(&["mm", "10", "expand"]))
+        .unwrap()
+        .unwrap();
+    assert_eq!((res.manu_mat_level, res.expand), (10, true));
+    assert!(origin.apply_follow_up(tokens(&["谢谢"])).is_none());
+    assert!(origin.apply_follow_up(tokens(&["10"])).is_none());
+    assert_eq!(
+        origin
+            .apply_follow_up(tokens(&["tq", "10", "mm", "11", "pattern", "a"]))
+            .unwrap()
+            .unwrap_err()
+            .as_ref()
+            .len(),
+        3
+    );
+    assert!(BlpMaterialPrice::SYNTAX_TEXT.contains("Default: se. Follow-up."));
+}
+
+#[evebot_proc_macro::create_syntax("syntax/command/blp_mat.json")]
 pub struct BlpMaterial;
 
 impl BlpMaterial {
@@ -52,7 +154,7 @@ impl BlpMaterial {
     ///     (manu_time_level <int>)?
     ///     (extra_mat <float>)?
     ///     (extra_time <float>)?
-    ///     (expand <bool>)?
+    ///     (expand | no-expand)?
     ///     (sql <bool>)?
     /// ```
     ///
@@ -60,58 +162,42 @@ impl BlpMaterial {
     /// - `manu_mat_level`: The level of all blueprints' material level. Value range: `[0, 10]`. Default value: 0.
     /// - `manu_time_level`: The level of all blueprints' time level. Value range: `[0, 10]`. Default value: 0.
     /// - `extra_mat`: The extra factor of all blueprints' material. Note: This is a percentage.
-    /// Value range: `[0.0, ...)`. Default value: 0.0.
+    ///   Value range: `[0.0, ...)`. Default value: 0.0.
     /// - `extra_time`: The extra factor of all blueprints' time. Note: This is a percentage.
-    /// Value range: `[0.0, ...)`. Default value: 0.0.
-    /// - `expand`: Whether to expand the blueprint. Default value: false.
+    ///   Value range: `[0.0, ...)`. Default value: 0.0.
+    /// - `expand`: Whether to expand the blueprint. A flag, off by default.
     /// - `sql`: Whether to use manual SQL pattern. Default value: false.
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
         let mut err_group = BotErrorGroup::new();
         let type_item = fetch_type! {
+            backend: backend,
             pattern: &self.pattern,
             type_name: &self.type_name,
             error: err_group,
         };
 
-        let value = BlueprintFactor::checked(
+        // ranges are enforced by the syntax
+        let value = BlueprintFactor::new(
             self.manu_mat_level as u8,
             self.manu_time_level as u8,
             self.extra_mat,
             self.extra_time,
-        )
-        .map_err(|mut e| err_group.as_mut().append(e.as_mut()))
-        .ok();
+        );
 
         if !err_group.as_ref().is_empty() || type_item.is_none() {
             return Err(err_group);
         }
 
         let type_item = type_item.unwrap();
-        let resp = reqwest::ClientBuilder::new()
-            .build()?
-            .post(format!(
-                "http://localhost:{}/blueprint/{}/{}/image/",
-                LOCAL_EVE_SERVICE_PORT,
-                type_item.type_id,
-                if self.expand { "recursive" } else { "plain" }
-            ))
-            .json(&value)
-            .send()
+        let image = backend
+            .blueprint_material_image(type_item.type_id, value, self.expand)
             .await?;
-        let file_name = random_filename();
-        download_image(
-            resp,
-            format!("{}{}.png", IMAGE_DIRECTORY, file_name).as_ref(),
-        )
-        .await?;
 
-        Ok(build_single_image! {
-            format!("file:///{}{}.png", IMAGE_DIRECTORY, file_name)
-        })
+        Ok(MessageChain::new().image(save_image(&image)?))
     }
 }
 
-#[evebot_proc_macro::create_syntax("./evebot-gocq-wrapper/syntax/command/blp_mat_price.json")]
+#[evebot_proc_macro::create_syntax("syntax/command/blp_mat_price.json")]
 pub struct BlpMaterialPrice;
 
 impl BlpMaterialPrice {
@@ -123,97 +209,53 @@ impl BlpMaterialPrice {
     ///     (manu_time_level <int>)?
     ///     (extra_mat <float>)?
     ///     (extra_time <float>)?
-    ///     (expand <bool>)?
+    ///     (expand | no-expand)?
     ///     (sql <bool>)?
-    ///     (server <enum>)?
+    ///     (server <server>)?
     /// ```
     ///
     /// - `item-name`: The name of the blueprint.
     /// - `manu_mat_level`: The level of all blueprints' material level. Value range: `[0, 10]`. Default value: 0.
     /// - `manu_time_level`: The level of all blueprints' time level. Value range: `[0, 10]`. Default value: 0.
     /// - `extra_mat`: The extra factor of all blueprints' material. Note: This is a percentage.
-    /// Value range: `[0.0, ...)`. Default value: 0.0.
+    ///   Value range: `[0.0, ...)`. Default value: 0.0.
     /// - `extra_time`: The extra factor of all blueprints' time. Note: This is a percentage.
-    /// Value range: `[0.0, ...)`. Default value: 0.0.
-    /// - `expand`: Whether to expand the blueprint. Default value: false.
+    ///   Value range: `[0.0, ...)`. Default value: 0.0.
+    /// - `expand`: Whether to expand the blueprint. A flag, off by default.
     /// - `sql`: Whether to use manual SQL pattern. Default value: false.
     /// - `server`: Which server to use. Possible value: 'se', 'tq'. Default value: 'se'.
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
         let mut err_group = BotErrorGroup::new();
 
         let type_item = fetch_type! {
+            backend: backend,
             pattern: &self.pattern,
             type_name: &self.type_name,
             error: err_group,
         };
 
-        let value = BlueprintFactor::checked(
+        // ranges are enforced by the syntax
+        let value = BlueprintFactor::new(
             self.manu_mat_level as u8,
             self.manu_time_level as u8,
             self.extra_mat,
             self.extra_time,
-        )
-        .map_err(|mut e| err_group.as_mut().append(e.as_mut()))
-        .ok();
-
-        let server = Server::parse_from(self.server)
-            .map_err(|e| err_group.push(e))
-            .ok();
+        );
 
         if !err_group.as_ref().is_empty() || type_item.is_none() {
             return Err(err_group);
         }
 
         let type_item = type_item.unwrap();
-        let value = value.unwrap();
-        let server = server.unwrap();
+        let server = self.server;
 
-        let material: serde_json::Value = reqwest::ClientBuilder::new()
-            .build()?
-            .post(format!(
-                "http://localhost:{}/blueprint/{}/{}/",
-                LOCAL_EVE_SERVICE_PORT,
-                type_item.type_id,
-                if self.expand { "recursive" } else { "plain" }
-            ))
-            .json(&value)
-            .send()
-            .await?
-            .json()
-            .await?;
-        let price: serde_json::Value = reqwest::ClientBuilder::new()
-            .build()?
-            .post(format!(
-                "http://localhost:{}/blueprint/market/?s={}",
-                LOCAL_EVE_SERVICE_PORT,
-                server.as_api_like()
-            ))
-            .json(&material)
-            .send()
-            .await?
-            .json()
+        let material = backend
+            .blueprint_material(type_item.type_id, value, self.expand)
             .await?;
-        let image_resp = reqwest::ClientBuilder::new()
-            .build()?
-            .post(format!(
-                "http://localhost:{}/blueprint/market/image/",
-                LOCAL_EVE_SERVICE_PORT
-            ))
-            .json(&price)
-            .send()
-            .await?;
-
-        let file_name = random_filename();
+        let price = backend.blueprint_market(server, &material).await?;
+        let image = backend.blueprint_market_image(&price).await?;
 
-        download_image(
-            image_resp,
-            format!("{}{}.png", IMAGE_DIRECTORY, file_name).as_ref(),
-        )
-        .await?;
-
-        Ok(build_single_image! {
-            format!("file:///{}{}.png", IMAGE_DIRECTORY, file_name)
-        })
+        Ok(MessageChain::new().image(save_image(&image)?))
     }
 }
 
@@ -226,96 +268,26 @@ fn test_blp_mat_price_image() {
         .build()
         .unwrap();
     println!("{}\n\n", BlpMaterialPrice::SYNTAX);
-    runtime.block_on(async {
+    let backend = crate::backend::MockBackend::default();
+    let content = |words: &[&str]| {
         let res = BlpMaterialPrice::parse(
-            [
-                ParamItem::Text("帕拉丁级蓝图".into()),
-                ParamItem::Text("pattern".into()),
-                ParamItem::Text("f".into()),
-                ParamItem::Text("expand".into()),
-                ParamItem::Text("yes".into()),
-            ]
-            .into_iter()
-            .peekable(),
-        );
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(image) => println!("{:#}", image),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-pub struct BlueprintFactor {
-    manu_material: u8,
-    manu_time: u8,
-    extra_material: f64,
-    extra_time: f64,
-}
-
-impl BlueprintFactor {
-    #[inline]
-    pub fn new(manu_material: u8, manu_time: u8, extra_material: f64, extra_time: f64) -> Self {
-        Self {
-            manu_time,
-            manu_material,
-            extra_material,
-            extra_time,
-        }
-    }
-
-    pub fn check(&self) -> Option<BotErrorGroup> {
-        let mut err_group = BotErrorGroup::new();
-        if self.manu_material > 10 {
-            err_group.push(BotError::Syntax {
-                found: Some(format!("{:?}", self.manu_material)),
-                expected: Some("Value out of range.".into()),
-                note: Some("Expect value in [0, 10]".into()),
-            })
-        }
-        if self.manu_time > 10 {
-            err_group.push(BotError::Syntax {
-                found: Some(format!("{:?}", self.manu_time)),
-                expected: Some("Value out of range.".into()),
-                note: Some("Expect value in [0, 10]".into()),
-            })
-        }
-        if self.extra_material < 0.0 {
-            err_group.push(BotError::Syntax {
-                found: Some(format!("{:?}", self.extra_material)),
-                expected: Some("Value out of range.".into()),
-                note: Some("Expect value in [0, ...)".into()),
-            })
-        }
-        if self.extra_time < 0.0 {
-            err_group.push(BotError::Syntax {
-                found: Some(format!("{:?}", self.extra_time)),
-                expected: Some("Value out of range.".into()),
-                note: Some("Expect value in [0, ...)".into()),
-            })
-        }
-        if err_group.as_ref().is_empty() {
-            None
-        } else {
-            Some(err_group)
-        }
-    }
-
-    #[inline]
-    pub fn checked(
-        manu_material: u8,
-        manu_time: u8,
-        extra_material: f64,
-        extra_time: f64,
-    ) -> BotGroupResult<Self> {
-        let val = Self::new(manu_material, manu_time, extra_material, extra_time);
-        if let Some(v) = val.check() {
-            Err(v)
-        } else {
-            Ok(val)
-        }
-    }
+            words
+                .iter()
+                .map(|s| ParamItem::Text(s.to_string()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .peekable(),
+        )
+        .unwrap();
+        runtime.block_on(res.get_content(&backend))
+    };
+    let image = crate::utils::fetch::take_image(
+        &content(&["帕拉丁级蓝图", "pattern", "f", "expand"]).unwrap(),
+    );
+    let price: serde_json::Value =
+        serde_json::from_str(image.strip_prefix("market ").unwrap()).unwrap();
+    assert_eq!(price["server"], "se");
+    assert_eq!(price["items"][0]["price"], 4.89);
+    // not a blueprint
+    assert!(content(&["三钛合金"]).is_err());
 }
@@ -0,0 +1,73 @@
+use crate::backend::EveBackend;
+use crate::error::BotGroupResult;
+use crate::server::MessageChain;
+
+#[evebot_proc_macro::create_syntax("syntax/command/bot_cache.json")]
+pub struct BotCache;
+
+#[test]
+fn test_bot_cache() {
+    use crate::backend::{CachedBackend, MockBackend};
+    use crate::constant::eve::server::Server;
+    use crate::server::{MessageData, ParamItem};
+    use serde_json::json;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let backend = CachedBackend::new(MockBackend::default(), &Default::default());
+    let content = |items: Vec<ParamItem>| {
+        let res = BotCache::parse(items.into_iter().peekable()).unwrap();
+        runtime
+            .block_on(res.get_content(&backend))
+            .unwrap()
+            .to_string()
+    };
+    runtime.block_on(async {
+        backend.jita_price(Server::Serenity, 34).await.unwrap();
+        backend.jita_price(Server::Serenity, 34).await.unwrap();
+    });
+    assert!(content(vec![]).contains("价格：1 条  命中：1  未命中：1"));
+    let flushed = content(vec![ParamItem::Text("flush".into())]);
+    assert!(flushed.starts_with("已清空 1 条缓存"));
+    assert!(flushed.contains("价格：0 条  命中：1  未命中：1"));
+
+    // only admins may ask
+    let data = MessageData(json! {[{ "type": "text", "data": { "text": "eve cache" } }]});
+    assert!(runtime
+        .block_on(super::dis::distribute(
+            data.into_messages().unwrap(),
+            &crate::config::config().account(0),
+            super::dis::PrefixMode::Required,
+            crate::command::Sender::default(),
+            &backend,
+        ))
+        .is_none());
+}
+
+impl BotCache {
+    /// # Syntax
+    ///
+    /// ```
+    /// eve cache (flush)?
+    /// ```
+    ///
+    /// - `flush`: Drop every cached answer first.
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
+        let Some(cache) = backend.cache() else {
+            return Ok(MessageChain::new().text("后端没有缓存"));
+        };
+        let mut text = String::new();
+        if self.flush {
+            text.push_str(&format!("已清空 {} 条缓存\n", cache.flush()));
+        }
+        text.push_str("缓存状态");
+        for (kind, stats) in cache.stats() {
+            text.push_str(&format!(
+                "\n{}：{} 条  命中：{}  未命中：{}",
+                kind, stats.entries, stats.hits, stats.misses
+            ));
+        }
+        Ok(MessageChain::new().text(text))
+    }
+}
@@ -1,16 +1,22 @@
-use crate::command::BotService;
-use crate::error::BotGroupResult;
-use crate::metadata::BOT_UID;
-use crate::server::ParamItem;
+use crate::backend::EveBackend;
+use crate::command::{
+    check_limit, run_output, take_choice, BotService, CommandLimit, CommandOutput, Context, Sender,
+};
+use crate::config::{config, Account};
+use crate::error::{BotGroupResult, BotResult};
+use crate::server::{ParamItem, Token};
+use std::collections::{BTreeMap, VecDeque};
 
 #[test]
 fn test_distribute() {
-    use crate::server::MessageData;
+    use crate::backend::MockBackend;
+    use crate::server::{MessageChain, MessageData};
     use serde_json::json;
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
+    let backend = MockBackend::default();
     runtime.block_on(async {
         let text = MessageData(json! {[
             {
@@ -20,53 +26,475 @@ fn test_distribute() {
                 }
             },
         ]});
-        let res = distribute(text.into_messages().unwrap())
-            .await
-            .unwrap_or_else(|| {
-                Ok(json! {[{
-                    "type": "text",
-                    "data": {
-                        "text": "<NO-DATA>"
-                    }
-                }]})
+        let res = distribute(
+            text.into_messages().unwrap(),
+            &config().account(0),
+            PrefixMode::Required,
+            Sender::default(),
+            &backend,
+        )
+        .await
+        .unwrap_or_else(|| Ok(MessageChain::new().text("<NO-DATA>").into()))
+        .map(|output| output.message)
+        .unwrap_or_else(|e| MessageChain::new().text(e.to_string()));
+        println!("{}", res);
+        assert!(crate::utils::fetch::take_image(&res).starts_with("blueprint 23774 "));
+    });
+}
+
+#[test]
+fn test_distribute_suggestion() {
+    use crate::backend::MockBackend;
+    use crate::error::BotError;
+    use crate::server::MessageData;
+    use serde_json::json;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let backend = MockBackend::default();
+    let syntax_errors = |text: &str| {
+        let data = MessageData(json! {[{ "type": "text", "data": { "text": text } }]});
+        runtime
+            .block_on(distribute(
+                data.into_messages().unwrap(),
+                &config().account(0),
+                PrefixMode::Required,
+                Sender::default(),
+                &backend,
+            ))
+            .map(|res| {
+                res.unwrap_err()
+                    .into_inner()
+                    .into_iter()
+                    .map(|e| match e {
+                        BotError::Syntax {
+                            found, expected, ..
+                        } => (found.unwrap(), expected),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>()
             })
-            .unwrap_or_else(|e| {
-                json! {[{
-                    "type": "text",
-                    "data": {
-                        "text": e.to_string()
-                    }
-                }]}
-            });
-        println!(
-            "{}",
-            res.get(0)
-                .and_then(|s| s.get("data"))
-                .and_then(|s| s.get("text").or_else(|| s.get("file")))
-                .and_then(|s| s.as_str())
-                .unwrap()
-        );
+    };
+
+    assert_eq!(syntax_errors("eve hello"), None);
+    // the prefix is optional in private chats
+    let data = MessageData(json! {[{ "type": "text", "data": { "text": "markte jita" } }]});
+    assert!(runtime
+        .block_on(distribute(
+            data.clone().into_messages().unwrap(),
+            &config().account(0),
+            PrefixMode::Required,
+            Sender::default(),
+            &backend
+        ))
+        .is_none());
+    assert!(runtime
+        .block_on(distribute(
+            data.into_messages().unwrap(),
+            &config().account(0),
+            PrefixMode::Optional,
+            Sender::default(),
+            &backend
+        ))
+        .is_some_and(|res| res.is_err()));
+    // glued prefixes, and commands without a prefix where allowed
+    let account = crate::config::Account {
+        prefixes: vec!["eve".into(), ".".into()],
+        ..config().account(0)
+    };
+    let answered = |text: &str, mode| {
+        let data = MessageData(json! {[{ "type": "text", "data": { "text": text } }]});
+        runtime
+            .block_on(distribute(
+                data.into_messages().unwrap(),
+                &account,
+                mode,
+                Sender::default(),
+                &backend,
+            ))
+            .is_some()
+    };
+    assert!(answered(".status", PrefixMode::Required));
+    assert!(!answered("status", PrefixMode::Required));
+    let prefixless = ["status".to_string()];
+    assert!(answered("status", PrefixMode::Only(&prefixless)));
+    assert!(!answered("market jita 34", PrefixMode::Only(&prefixless)));
+    assert!(answered("eve market jita", PrefixMode::Only(&prefixless)));
+
+    // a disabled command is ignored, not suggested
+    let account = crate::config::Account {
+        groups: vec!["status".into()],
+        ..config().account(0)
+    };
+    for text in ["eve market jita 34", "eve markt jita 34"] {
+        let data = MessageData(json! {[{ "type": "text", "data": { "text": text } }]});
+        assert!(runtime
+            .block_on(distribute(
+                data.into_messages().unwrap(),
+                &account,
+                PrefixMode::Required,
+                Sender::default(),
+                &backend
+            ))
+            .is_none());
+    }
+    assert_eq!(
+        syntax_errors("eve markte jita 34"),
+        Some(vec![("markte".into(), Some("market".into()))])
+    );
+    assert_eq!(
+        syntax_errors("eve item tpye id 34"),
+        Some(vec![("tpye".into(), Some("type".into()))])
+    );
+    assert_eq!(
+        syntax_errors("eve item type id 34 patern f sql"),
+        Some(vec![
+            ("patern".into(), Some("pattern".into())),
+            ("sql".into(), Some("pattern 的取值".into())),
+        ])
+    );
+    assert_eq!(
+        syntax_errors("eve item type id 34 pat f pattern absolute"),
+        Some(vec![("pattern".into(), Some("pattern".into()))])
+    );
+
+    let data = MessageData(json! {[{ "type": "text", "data": { "text": "eve markte (jita" } }]});
+    let err = runtime
+        .block_on(distribute(
+            data.into_messages().unwrap(),
+            &config().account(0),
+            PrefixMode::Required,
+            Sender::default(),
+            &backend,
+        ))
+        .unwrap()
+        .unwrap_err();
+    assert!(matches!(
+        err.errors(),
+        [BotError::Syntax { span: Some(span), .. }] if (span.start, span.end) == (11, 16)
+    ));
+    assert!(err
+        .render(&["eve markte (jita".into()])
+        .ends_with("eve markte (jita\n           ^^^^^"));
+}
+
+#[test]
+fn test_choose() {
+    use crate::backend::MockBackend;
+    use crate::command::save_pending;
+    use crate::server::MessageData;
+    use serde_json::json;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let backend = MockBackend::default();
+    let account = config().account(0);
+    let sender = Sender {
+        user_id: 24,
+        group_id: Some(24),
+    };
+    let tokens = |text: &str| {
+        MessageData(json! {[{ "type": "text", "data": { "text": text } }]})
+            .into_messages()
+            .unwrap()
+    };
+    let output = runtime
+        .block_on(distribute(
+            tokens("eve market jita 三钛"),
+            &account,
+            PrefixMode::Required,
+            sender,
+            &backend,
+        ))
+        .unwrap()
+        .unwrap();
+    assert!(output
+        .message
+        .to_string()
+        .ends_with("\n1. 三钛合金\n2. 三钛合金测试样品"));
+    save_pending(account.self_id, sender, output.pending.unwrap());
+
+    let choose =
+        |text: &str| runtime.block_on(choose(account.self_id, sender, tokens(text), &backend));
+    assert!(choose("1 2").is_none());
+    assert!(choose("3").is_none());
+    let output = choose(" 1 ").unwrap().unwrap();
+    assert!(output
+        .message
+        .to_string()
+        .starts_with("物品价格（晨曦）  三钛合金"));
+    // the answer can be followed up on with the chosen type
+    assert!(output.context.is_some());
+    assert!(choose("1").is_none());
+}
+
+#[test]
+fn test_follow_up_keeps_type() {
+    use crate::backend::{CachedBackend, EveBackend, MockBackend};
+    use crate::server::MessageData;
+    use serde_json::json;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    // counts every search, as a miss or a hit
+    let backend = CachedBackend::new(MockBackend::default(), &Default::default());
+    let searches = || {
+        let stats = backend.cache().unwrap().stats()[0].1;
+        stats.hits + stats.misses
+    };
+    let account = config().account(0);
+    let tokens = |text: &str| {
+        MessageData(json! {[{ "type": "text", "data": { "text": text } }]})
+            .into_messages()
+            .unwrap()
+    };
+    let output = runtime
+        .block_on(distribute(
+            tokens("eve market jita 三钛合金"),
+            &account,
+            PrefixMode::Required,
+            Sender::default(),
+            &backend,
+        ))
+        .unwrap()
+        .unwrap();
+    assert_eq!(searches(), 1);
+    let output = runtime
+        .block_on(follow_up(
+            output.context.unwrap(),
+            tokens("tq"),
+            &account,
+            Sender::default(),
+            &backend,
+        ))
+        .unwrap()
+        .unwrap();
+    assert!(output.message.to_string().contains("三钛合金"));
+    assert_eq!(searches(), 1);
+}
+
+#[test]
+fn test_expand_alias() {
+    use crate::server::Span;
+    let aliases = [("jita".to_string(), "eve market jita".to_string())].into();
+    let span = Some(Span {
+        segment: 0,
+        start: 1,
+        end: 5,
     });
+    let mut tokens = ["jita", "三钛合金"]
+        .map(|t| {
+            Ok(Token {
+                item: ParamItem::Text(t.into()),
+                span,
+            })
+        })
+        .into_iter()
+        .collect::<VecDeque<_>>();
+    expand_alias(&mut tokens, &config().account(0), &aliases);
+    let words = tokens
+        .into_iter()
+        .map(|t| t.unwrap())
+        .map(|t| (t.item, t.span))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        words,
+        vec![
+            (ParamItem::Text("market".into()), span),
+            (ParamItem::Text("jita".into()), span),
+            (ParamItem::Text("三钛合金".into()), span),
+        ]
+    );
+}
+
+/// Which messages are taken as commands without a prefix or an `@` of the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixMode<'a> {
+    Required,
+    /// Every message, as in private chats.
+    Optional,
+    /// Only those starting with one of these commands or aliases.
+    Only(&'a [String]),
+}
+
+impl PrefixMode<'_> {
+    fn allows(&self, command: &str) -> bool {
+        match self {
+            Self::Required => false,
+            Self::Optional => true,
+            Self::Only(commands) => commands.iter().any(|c| c == command),
+        }
+    }
+}
+
+/// Replace an alias at the front of `tokens` with what it stands for.
+///
+/// The words of the alias take its span, so errors in them point at the alias.
+fn expand_alias(
+    tokens: &mut VecDeque<BotResult<Token>>,
+    account: &Account,
+    aliases: &BTreeMap<String, String>,
+) {
+    let Some(Ok(Token {
+        item: ParamItem::Text(alias),
+        span,
+    })) = tokens.front()
+    else {
+        return;
+    };
+    let Some(expansion) = aliases.get(alias) else {
+        return;
+    };
+    let span = *span;
+    let bot = &config().bot;
+    let mut words = expansion.split_whitespace().peekable();
+    // the prefix is optional in an alias
+    if words
+        .peek()
+        .is_some_and(|w| account.is_prefix(w) || bot.prefixes.iter().any(|p| p == w))
+    {
+        words.next();
+    }
+    let words = words
+        .map(|w| {
+            Ok(Token {
+                item: ParamItem::Text(w.to_owned()),
+                span,
+            })
+        })
+        .collect::<Vec<_>>();
+    tokens.pop_front();
+    for word in words.into_iter().rev() {
+        tokens.push_front(word);
+    }
 }
 
+/// Run the command in `param`, received by `account` from `sender`. `mode` tells whether the
+/// message must start with a prefix, as a word of its own or glued to the command (`.jita`), or
+/// with an `@` of the bot.
 pub async fn distribute(
-    param: impl Iterator<Item = ParamItem>,
-) -> Option<BotGroupResult<serde_json::Value>> {
-    let mut param = param.peekable();
-    // Prefix
-    match &param.next()? {
-        ParamItem::Text(_t) if _t.as_str() != "eve" => return None,
-        ParamItem::At(a) => {
-            if *a != BOT_UID {
-                return None;
-            } else if param.peek() == Some(&ParamItem::Text("eve".into())) {
-                param.next();
+    param: impl Iterator<Item = BotResult<Token>>,
+    account: &Account,
+    mode: PrefixMode<'_>,
+    sender: Sender,
+    backend: &dyn EveBackend,
+) -> Option<BotGroupResult<CommandOutput>> {
+    // the iterator ends at the first error, so this is never much
+    let mut tokens = param.collect::<VecDeque<_>>();
+    let group_enabled = |ident: &str| account.group_enabled(ident);
+    let throttle = |limit: &CommandLimit| check_limit(sender, limit);
+    let admin = || config().bot.admins.contains(&sender.user_id);
+    // Prefix. A message that fails to tokenize is only reported if it is meant for the bot.
+    let mut addressed = false;
+    if let ParamItem::At(a) = &tokens.front()?.as_ref().ok()?.item {
+        if *a != account.self_id {
+            return None;
+        }
+        tokens.pop_front();
+        addressed = true;
+    }
+    if let Some(Ok(Token {
+        item: ParamItem::Text(_t),
+        span,
+    })) = tokens.front_mut()
+    {
+        if account.is_prefix(_t) {
+            tokens.pop_front();
+            addressed = true;
+        } else if let Some(prefix_len) = account.glued_prefix(_t).map(str::len) {
+            let skipped = _t[..prefix_len].chars().count();
+            _t.drain(..prefix_len);
+            if let Some(span) = span {
+                span.start += skipped;
             }
+            addressed = true;
+        }
+    }
+    if !addressed {
+        match tokens.front() {
+            Some(Ok(Token {
+                item: ParamItem::Text(_t),
+                ..
+            })) if mode.allows(_t) => {}
+            _ => return None,
+        }
+    }
+    expand_alias(&mut tokens, account, &config().aliases);
+    let mut param = match tokens.into_iter().collect::<BotResult<Vec<_>>>() {
+        Ok(tokens) => tokens.into_iter().peekable(),
+        Err(err) => return Some(Err(err.into())),
+    };
+
+    evebot_proc_macro::create_distributor!("src/command/distributor/distributor.json")
+}
+
+/// Re-run the command of `context` with the changes in `param`, a quote-reply to its answer.
+///
+/// Returns `None` if `param` starts with a prefix, as a new command, or is not a follow-up.
+pub async fn follow_up(
+    context: Context,
+    param: impl Iterator<Item = BotResult<Token>>,
+    account: &Account,
+    sender: Sender,
+    backend: &dyn EveBackend,
+) -> Option<BotGroupResult<CommandOutput>> {
+    // quoting a message mentions its sender
+    let mut param = param
+        .skip_while(
+            |item| matches!(item, Ok(Token { item: ParamItem::At(a), .. }) if *a == account.self_id),
+        )
+        .peekable();
+    if let Some(Ok(Token {
+        item: ParamItem::Text(_t),
+        ..
+    })) = param.peek()
+    {
+        if account.is_prefix(_t) || account.glued_prefix(_t).is_some() {
+            return None;
         }
-        _ => {}
     }
+    let tokens = match param.collect::<BotResult<Vec<_>>>() {
+        Ok(tokens) => tokens,
+        Err(err) => return Some(Err(err.into())),
+    };
+    let context = match context.follow_up(tokens)? {
+        Ok(context) => context,
+        Err(err) => return Some(Err(err)),
+    };
+    if let Some(limit) = context.limit() {
+        if let Err(notice) = check_limit(sender, &limit) {
+            return notice.map(|message| Ok(message.into()));
+        }
+    }
+    Some(run_output(context.clone(), context.run(backend).await))
+}
 
-    evebot_proc_macro::create_distributor!(
-        r"D:\WBH\rust\evebot\evebot-gocq-wrapper\src\command\distributor\distributor.json"
-    )
+/// Run the command waiting for `sender` to choose a type, if `param` is only the number of one
+/// of its candidates, see [`crate::command::Pending`].
+///
+/// The command was already throttled when it asked, so it is not again.
+pub async fn choose(
+    self_id: u64,
+    sender: Sender,
+    param: impl Iterator<Item = BotResult<Token>>,
+    backend: &dyn EveBackend,
+) -> Option<BotGroupResult<CommandOutput>> {
+    let mut param = param.skip_while(
+        |item| matches!(item, Ok(Token { item: ParamItem::At(a), .. }) if *a == self_id),
+    );
+    let choice = match param.next()? {
+        Ok(Token {
+            item: ParamItem::Text(t),
+            ..
+        }) => t.trim().parse().ok()?,
+        _ => return None,
+    };
+    if param.next().is_some() {
+        return None;
+    }
+    let context = take_choice(self_id, sender, choice)?;
+    Some(run_output(context.clone(), context.run(backend).await))
 }
@@ -1,45 +1,85 @@
-use crate::constant::eve::server::Server;
+use crate::backend::EveBackend;
 use crate::error::{BotErrorGroup, BotGroupResult};
-use crate::metadata::LOCAL_EVE_SERVICE_PORT;
+use crate::fetch_type;
+use crate::server::MessageChain;
 use crate::utils::numeric::format_price;
-use crate::{build_single_text, fetch_type};
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-pub struct MarketPrice {
-    buy: f64,
-    sell: f64,
-    medium: f64,
-}
 
 #[test]
 fn test_jita_price() {
+    use crate::backend::MockBackend;
     use crate::server::ParamItem;
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
-    runtime.block_on(async {
+    let backend = MockBackend::default();
+    let content = |words: &[&str]| {
         let res = JitaPrice::parse(
-            [
-                ParamItem::Text("三%金".into()),
-                ParamItem::Text("pattern".into()),
-                ParamItem::Text("m".into()),
-            ]
+            words
+                .iter()
+                .map(|s| ParamItem::Text(s.to_string()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .peekable(),
+        )
+        .unwrap();
+        runtime
+            .block_on(res.get_content(&backend))
+            .map(|message| message.to_string())
+            .map_err(|err| err.to_string())
+    };
+    assert_eq!(
+        content(&["三%金", "pattern", "m"]).unwrap(),
+        content(&["三钛合金"]).unwrap()
+    );
+    assert!(content(&["三钛合金", "server", "tq"])
+        .unwrap()
+        .starts_with("物品价格（宁静）  三钛合金\n"));
+    // no such type, or no price on that server
+    assert!(content(&["不存在的物品"]).is_err());
+    assert!(content(&["Raven Navy Issue", "server", "tq"]).is_err());
+}
+
+#[test]
+fn test_jita_price_greedy_name() {
+    use crate::constant::eve::server::Server;
+    use crate::server::ParamItem;
+    let res = JitaPrice::parse(
+        ["Raven", "Navy", "Issue", "server", "tq"]
+            .map(|s| ParamItem::Text(s.into()))
             .into_iter()
             .peekable(),
-        );
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(price) => println!("{:#}", price),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
+    )
+    .unwrap();
+    assert_eq!(res.type_name, "Raven Navy Issue");
+    assert_eq!(res.server, Server::Tranquility);
+
+    // the account's default only applies when the parameter is left out
+    let account = crate::config::Account {
+        defaults: [("server".to_string(), "tq".to_string())].into(),
+        ..Default::default()
+    };
+    let parse = |words: &'static [&'static str]| {
+        let account = account.clone();
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(crate::command::with_account(account, async move {
+                JitaPrice::parse(
+                    words
+                        .iter()
+                        .map(|s| ParamItem::Text(s.to_string()))
+                        .peekable(),
+                )
+                .unwrap()
+                .server
+            }))
+    };
+    assert_eq!(parse(&["Raven"]), Server::Tranquility);
+    assert_eq!(parse(&["Raven", "server", "se"]), Server::Serenity);
 }
 
-#[evebot_proc_macro::create_syntax("./evebot-gocq-wrapper/syntax/command/market_jita.json")]
+#[evebot_proc_macro::create_syntax("syntax/command/market_jita.json")]
 pub struct JitaPrice;
 
 impl JitaPrice {
@@ -52,61 +92,39 @@ impl JitaPrice {
     /// - `item-name`: The name of the item.
     /// - `server`: The name of the server. Possible values: 'se', 'tq'. Default value: 'se'.
     /// - `sql`: Whether to use manual SQL pattern. Default value: false.
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
         let mut err_group = BotErrorGroup::new();
-        let server = match Server::parse_from(self.server) {
-            Ok(val) => Some(val),
-            Err(err) => {
-                err_group.push(err);
-                None
-            }
-        };
+        let server = self.server;
         let type_item = fetch_type! {
+            backend: backend,
             pattern: &self.pattern,
             type_name: &self.type_name,
             error: err_group,
         };
-        if let Some(server) = server {
-            let price: Option<MarketPrice> = if let Some(type_item) = &type_item {
-                let resp = reqwest::get(format!(
-                    "http://localhost:{}/market/jita/type/{}/{}/",
-                    LOCAL_EVE_SERVICE_PORT,
-                    server.as_api_like(),
-                    type_item.type_id
-                ))
-                .await;
-                match resp {
-                    Ok(resp) => match resp.json().await {
-                        Ok(js) => Some(js),
-                        Err(err) => {
-                            err_group.push(err.into());
-                            None
-                        }
-                    },
-                    Err(err) => {
-                        err_group.push(err.into());
-                        None
-                    }
+        let price = if let Some(type_item) = &type_item {
+            match backend.jita_price(server, type_item.type_id).await {
+                Ok(price) => Some(price),
+                Err(err) => {
+                    err_group.push(err);
+                    None
                 }
-            } else {
-                None
-            };
-            match (type_item, price) {
-                (Some(type_item), Some(price)) => Ok(build_single_text!(format!(
-                    r"物品价格（{}）  {}
+            }
+        } else {
+            None
+        };
+        match (type_item, price) {
+            (Some(type_item), Some(price)) => Ok(MessageChain::new().text(format!(
+                r"物品价格（{}）  {}
 收单：{}
 卖单：{}
 中位价：{}",
-                    server.as_readable(),
-                    type_item.type_name,
-                    format_price(price.buy),
-                    format_price(price.sell),
-                    format_price(price.medium)
-                ))),
-                _ => Err(err_group),
-            }
-        } else {
-            Err(err_group)
+                server.as_readable(),
+                type_item.type_name,
+                format_price(price.buy),
+                format_price(price.sell),
+                format_price(price.medium)
+            ))),
+            _ => Err(err_group),
         }
     }
 }
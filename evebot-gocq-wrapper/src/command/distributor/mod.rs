@@ -1,18 +1,15 @@
 mod blp;
+mod cache;
 pub mod dis;
 mod market;
 mod skills;
+mod status;
 mod type_item;
 
 use crate::error::BotError;
-use serde_json::json;
+use crate::server::MessageChain;
 
 #[allow(dead_code)]
-pub fn generic_response() -> Result<serde_json::Value, BotError> {
-    Ok(json! {[{
-        "type": "text",
-        "data": {
-            "text": "Hello, world!"
-        }
-    }]})
+pub fn generic_response() -> Result<MessageChain, BotError> {
+    Ok(MessageChain::new().text("Hello, world!"))
 }
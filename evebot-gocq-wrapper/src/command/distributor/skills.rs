@@ -1,8 +1,10 @@
-use crate::build_single_image;
-use crate::error::BotGroupResult;
-use crate::metadata::{IMAGE_DIRECTORY, LOCAL_EVE_SERVICE_PORT};
+use crate::backend::EveBackend;
+use crate::error::{BotErrorGroup, BotGroupResult};
+use crate::fetch_type;
+use crate::server::MessageChain;
+use crate::utils::fetch::save_image;
 
-#[evebot_proc_macro::create_syntax("evebot-gocq-wrapper/syntax/command/skill_item.json")]
+#[evebot_proc_macro::create_syntax("syntax/command/skill_item.json")]
 pub struct Skill;
 
 #[test]
@@ -14,24 +16,23 @@ fn test_skill_image() {
         .build()
         .unwrap();
     println!("{}\n\n", Skill::SYNTAX);
-    runtime.block_on(async {
-        let res = Skill::parse(
-            [
-                ParamItem::Text("帕拉丁级".into()),
-                ParamItem::Text("pattern".into()),
-                ParamItem::Text("f".into()),
-            ]
-            .into_iter()
-            .peekable(),
-        );
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(image) => println!("{:#}", image),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
+    let res = Skill::parse(
+        [
+            ParamItem::Text("帕拉丁级".into()),
+            ParamItem::Text("pattern".into()),
+            ParamItem::Text("f".into()),
+        ]
+        .into_iter()
+        .peekable(),
+    )
+    .unwrap();
+    let image = runtime
+        .block_on(res.get_content(&crate::backend::MockBackend::default()))
+        .unwrap();
+    assert_eq!(
+        crate::utils::fetch::take_image(&image),
+        "skills of 帕拉丁级"
+    );
 }
 
 impl Skill {
@@ -43,21 +44,16 @@ impl Skill {
     ///
     /// - `item-name`: The name of the item.
     /// - `sql`: Whether to use manual SQL pattern. Default value: false.
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
-        let type_item = crate::utils::fetch::TypeIDFetch::type_from(&self.pattern)(&self.type_name)?
-            .get()
-            .await?;
-        let img_raw = reqwest::get(format!(
-            "http://localhost:{}/skill/prereq/{}/image/",
-            LOCAL_EVE_SERVICE_PORT, type_item.type_id,
-        ))
-        .await?;
-        let file_name = crate::utils::fetch::random_filename();
-        crate::utils::fetch::download_image(
-            img_raw,
-            format!("{}{}.png", IMAGE_DIRECTORY, file_name).as_ref(),
-        )
-        .await?;
-        Ok(build_single_image! {format!("file:///{}{}.png", IMAGE_DIRECTORY, file_name)})
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
+        let mut err_group = BotErrorGroup::new();
+        let type_item = fetch_type! {
+            backend: backend,
+            pattern: &self.pattern,
+            type_name: &self.type_name,
+            error: err_group,
+        }
+        .ok_or(err_group)?;
+        let image = backend.skill_image(type_item.type_id).await?;
+        Ok(MessageChain::new().image(save_image(&image)?))
     }
 }
@@ -0,0 +1,76 @@
+use crate::backend::EveBackend;
+use crate::command::format_duration;
+use crate::error::BotGroupResult;
+use crate::server::{account_statuses, MessageChain};
+use std::time::Instant;
+
+#[evebot_proc_macro::create_syntax("syntax/command/bot_status.json")]
+pub struct BotStatus;
+
+#[test]
+fn test_bot_status() {
+    use crate::server::{track_meta, MessageData, MetaEvent};
+    use serde_json::json;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    track_meta(&MetaEvent::Heartbeat {
+        self_id: 10086,
+        interval: 5000,
+        status: json! {{ "online": true, "good": true }},
+    });
+    let data = MessageData(json! {[{ "type": "text", "data": { "text": "eve status" } }]});
+    let message = runtime
+        .block_on(super::dis::distribute(
+            data.into_messages().unwrap(),
+            &crate::config::config().account(0),
+            super::dis::PrefixMode::Required,
+            crate::command::Sender::default(),
+            &crate::backend::MockBackend::default(),
+        ))
+        .unwrap()
+        .unwrap()
+        .message
+        .to_string();
+    assert!(message.contains("10086：在线"));
+}
+
+impl BotStatus {
+    /// # Syntax
+    ///
+    /// ```
+    /// eve status
+    /// ```
+    pub async fn get_content(&self, _backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
+        let now = Instant::now();
+        let accounts = account_statuses();
+        if accounts.is_empty() {
+            return Ok(MessageChain::new().text("尚未收到任何账号的生命周期或心跳事件"));
+        }
+        let lines = accounts
+            .iter()
+            .map(|(self_id, status)| {
+                let state = if !status.online {
+                    "离线"
+                } else if status.overdue(now) {
+                    "心跳超时"
+                } else {
+                    "在线"
+                };
+                let heartbeat = match status.last_heartbeat {
+                    Some(t) => format!("{}前", format_duration(now.duration_since(t))),
+                    None => "无".into(),
+                };
+                format!(
+                    "{}：{}  运行：{}  上次心跳：{}",
+                    self_id,
+                    state,
+                    format_duration(now.duration_since(status.since)),
+                    heartbeat
+                )
+            })
+            .collect::<Vec<_>>();
+        Ok(MessageChain::new().text(format!("账号状态\n{}", lines.join("\n"))))
+    }
+}
@@ -1,16 +1,21 @@
-use crate::build_single_text;
-use crate::error::BotGroupResult;
-use crate::metadata::LOCAL_EVE_SERVICE_PORT;
-use crate::utils::fetch::{TypeIDFetch, TypeItem};
+use crate::backend::EveBackend;
+use crate::error::{BotErrorGroup, BotGroupResult};
+use crate::fetch_type;
+use crate::server::MessageChain;
 
 #[test]
 fn test_jita_price() {
+    use crate::backend::{MockBackend, TypeIDFetch, TypeItem};
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
+    let backend = MockBackend::default();
     runtime.block_on(async {
-        let res: TypeItem = TypeIDFetch::Absolute("三钛合金").get().await.unwrap();
+        let res: TypeItem = backend
+            .search_type(TypeIDFetch::Absolute("三钛合金"))
+            .await
+            .unwrap();
         // println!("{:?}", res);
         assert_eq!(
             res,
@@ -20,7 +25,10 @@ fn test_jita_price() {
                 published: true
             }
         );
-        let res: TypeItem = TypeIDFetch::Fuzzy("三钛").get().await.unwrap();
+        let res: TypeItem = backend
+            .search_type(TypeIDFetch::Fuzzy("三钛"))
+            .await
+            .unwrap();
         // println!("{:?}", res);
         assert_eq!(
             res,
@@ -30,7 +38,10 @@ fn test_jita_price() {
                 published: true
             }
         );
-        let res: TypeItem = TypeIDFetch::Manual("三%合%").get().await.unwrap();
+        let res: TypeItem = backend
+            .search_type(TypeIDFetch::Manual("三%合%"))
+            .await
+            .unwrap();
         // println!("{:?}", res);
         assert_eq!(
             res,
@@ -40,28 +51,26 @@ fn test_jita_price() {
                 published: true
             }
         );
+        assert!(backend
+            .search_type(TypeIDFetch::Absolute("三钛"))
+            .await
+            .is_err());
     });
 }
 
-#[evebot_proc_macro::create_syntax("evebot-gocq-wrapper/syntax/command/type_fetch_id.json")]
+#[evebot_proc_macro::create_syntax("syntax/command/type_fetch_id.json")]
 pub struct TypeFetchId;
 
 impl TypeFetchId {
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
-        let type_item: TypeItem = reqwest::get(format!(
-            "http://localhost:{}/types/{}/",
-            LOCAL_EVE_SERVICE_PORT, self.type_id
-        ))
-        .await?
-        .json()
-        .await?;
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
+        let type_item = backend.type_by_id(self.type_id as usize).await?;
         let text = format!(
             "物品ID：{}\n物品名称：{}\n是否公开：{}",
             type_item.type_id,
             type_item.type_name,
             if type_item.published { "是" } else { "否" }
         );
-        Ok(build_single_text!(text))
+        Ok(MessageChain::new().text(text))
     }
 }
 
@@ -74,33 +83,41 @@ fn test_type_getter_id() {
         .build()
         .unwrap();
     println!("{}\n\n", TypeFetchId::SYNTAX);
-    runtime.block_on(async {
-        let res = TypeFetchId::parse([ParamItem::Text("34".into())].into_iter().peekable());
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(val) => println!("{:#}", val),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
+    let backend = crate::backend::MockBackend::default();
+    let content = |id: &str| {
+        let res = TypeFetchId::parse([ParamItem::Text(id.into())].into_iter().peekable()).unwrap();
+        runtime
+            .block_on(res.get_content(&backend))
+            .map(|val| val.to_string())
+    };
+    assert_eq!(
+        content("34").unwrap(),
+        "物品ID：34\n物品名称：三钛合金\n是否公开：是"
+    );
+    assert!(content("41236").unwrap().ends_with("是否公开：否"));
+    assert!(content("1").is_err());
 }
 
-#[evebot_proc_macro::create_syntax("evebot-gocq-wrapper/syntax/command/type_fetch_name.json")]
+#[evebot_proc_macro::create_syntax("syntax/command/type_fetch_name.json")]
 pub struct TypeFetchName;
 
 impl TypeFetchName {
-    pub async fn get_content(&self) -> BotGroupResult<serde_json::Value> {
-        let type_item: TypeItem = TypeIDFetch::type_from(&self.pattern)(&self.type_name)?
-            .get()
-            .await?;
+    pub async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
+        let mut err_group = BotErrorGroup::new();
+        let type_item = fetch_type! {
+            backend: backend,
+            pattern: &self.pattern,
+            type_name: &self.type_name,
+            error: err_group,
+        }
+        .ok_or(err_group)?;
         let text = format!(
             "物品ID：{}\n物品名称：{}\n是否公开：{}",
             type_item.type_id,
             type_item.type_name,
             if type_item.published { "是" } else { "否" }
         );
-        Ok(build_single_text!(text))
+        Ok(MessageChain::new().text(text))
     }
 }
 
@@ -113,22 +130,21 @@ fn test_type_getter_name() {
         .build()
         .unwrap();
     println!("{}\n\n", TypeFetchName::SYNTAX);
-    runtime.block_on(async {
-        let res = TypeFetchName::parse(
-            [
-                ParamItem::Text("三钛%金".into()),
-                ParamItem::Text("pattern".into()),
-                ParamItem::Text("m".into()),
-            ]
-            .into_iter()
-            .peekable(),
-        );
-        match res {
-            Ok(res) => match res.get_content().await {
-                Ok(val) => println!("{:#}", val),
-                Err(err) => println!("{}", err),
-            },
-            Err(err) => println!("{}", err),
-        }
-    });
+    let res = TypeFetchName::parse(
+        [
+            ParamItem::Text("三钛%金".into()),
+            ParamItem::Text("pattern".into()),
+            ParamItem::Text("m".into()),
+        ]
+        .into_iter()
+        .peekable(),
+    )
+    .unwrap();
+    let val = runtime
+        .block_on(res.get_content(&crate::backend::MockBackend::default()))
+        .unwrap();
+    assert_eq!(
+        val.to_string(),
+        "物品ID：34\n物品名称：三钛合金\n是否公开：是"
+    );
 }
@@ -0,0 +1,372 @@
+use crate::backend::EveBackend;
+use crate::command::{Context, FollowUp};
+use crate::config::{config, BucketConfig, RateLimitConfig};
+use crate::error::BotGroupResult;
+use crate::server::{MessageChain, Token};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many checks go between two sweeps of the idle entries of a [`RateLimiter`].
+const PRUNE_INTERVAL: u32 = 1024;
+
+/// Who sent a command, for rate limiting and choosing between candidates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Sender {
+    pub user_id: u64,
+    /// `None` in private chats.
+    pub group_id: Option<u64>,
+}
+
+/// The `cost` and `cooldown` of a subcommand, declared in `distributor.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandLimit {
+    /// The path of the subcommand, e.g. `blueprint.price`.
+    pub command: &'static str,
+    /// Tokens taken from each bucket. `0` for no limit at all.
+    pub cost: u32,
+    /// Seconds before the same user can run the command again.
+    pub cooldown: u64,
+}
+
+impl CommandLimit {
+    /// Keep the limit with `context`, so that following up on the answer is limited too.
+    pub fn wrap(self, context: Context) -> Context {
+        Arc::new(Limited {
+            limit: self,
+            inner: context,
+        })
+    }
+}
+
+struct Limited {
+    limit: CommandLimit,
+    inner: Context,
+}
+
+impl FollowUp for Limited {
+    fn follow_up(&self, param: Vec<Token>) -> Option<BotGroupResult<Context>> {
+        Some(
+            self.inner
+                .follow_up(param)?
+                .map(|context| self.limit.wrap(context)),
+        )
+    }
+
+    fn run<'a>(
+        &'a self,
+        backend: &'a dyn EveBackend,
+    ) -> Pin<Box<dyn Future<Output = BotGroupResult<MessageChain>> + 'a>> {
+        self.inner.run(backend)
+    }
+
+    fn limit(&self) -> Option<CommandLimit> {
+        Some(self.limit)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(config: &BucketConfig, now: Instant) -> Self {
+        Self {
+            tokens: config.capacity as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * config.per_minute as f64 / 60.0).min(config.capacity as f64);
+        self.updated = now;
+    }
+
+    /// Whether the bucket has refilled by `now`, so a new one would be the same.
+    fn is_full(&self, config: &BucketConfig, now: Instant) -> bool {
+        let mut bucket = *self;
+        bucket.refill(config, now);
+        bucket.tokens >= config.capacity as f64
+    }
+
+    /// How long until `cost` tokens are available, zero if they are.
+    fn wait(&self, config: &BucketConfig, cost: f64) -> Duration {
+        let missing = cost - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else if config.per_minute == 0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / config.per_minute as f64)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BucketKey {
+    User(u64),
+    Group(u64),
+    Global,
+}
+
+impl BucketKey {
+    fn config(self, config: &RateLimitConfig) -> &BucketConfig {
+        match self {
+            Self::User(_) => &config.user,
+            Self::Group(_) => &config.group,
+            Self::Global => &config.global,
+        }
+    }
+}
+
+/// The outcome of [`RateLimiter::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttle {
+    Allowed,
+    /// Try again after `retry_after`. `notify` is only set for the first refused command in a
+    /// row, so a spammer gets a single notice.
+    Throttled {
+        retry_after: Duration,
+        notify: bool,
+    },
+}
+
+/// Token buckets for each user, each group and everyone, and the cooldowns of each user.
+///
+/// Every [`PRUNE_INTERVAL`] checks, the entries that would be the same if they were created
+/// again are dropped, so idle users do not stay in memory.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<BucketKey, Bucket>,
+    /// When each user may run each command again.
+    cooldowns: HashMap<(u64, &'static str), Instant>,
+    notified: HashSet<u64>,
+    checks: u32,
+}
+
+impl RateLimiter {
+    /// Take the cost of `limit` from every bucket `sender` draws from, if all of them can
+    /// afford it.
+    pub fn check(
+        &mut self,
+        sender: Sender,
+        limit: &CommandLimit,
+        config: &RateLimitConfig,
+        now: Instant,
+    ) -> Throttle {
+        if limit.cost == 0 {
+            return Throttle::Allowed;
+        }
+        self.checks += 1;
+        if self.checks >= PRUNE_INTERVAL {
+            self.checks = 0;
+            self.prune(config, now);
+        }
+        let mut wait = match self.cooldowns.get(&(sender.user_id, limit.command)) {
+            Some(ready) => ready.saturating_duration_since(now),
+            None => Duration::ZERO,
+        };
+        let keys = [
+            Some((BucketKey::User(sender.user_id), &config.user)),
+            sender
+                .group_id
+                .map(|g| (BucketKey::Group(g), &config.group)),
+            Some((BucketKey::Global, &config.global)),
+        ];
+        let keys = keys
+            .into_iter()
+            .flatten()
+            .filter(|(_, c)| c.capacity > 0)
+            .collect::<Vec<_>>();
+        for (key, bucket_config) in &keys {
+            let bucket = self
+                .buckets
+                .entry(*key)
+                .or_insert_with(|| Bucket::full(bucket_config, now));
+            bucket.refill(bucket_config, now);
+            // a command costing more than the capacity could never run otherwise
+            let cost = limit.cost.min(bucket_config.capacity) as f64;
+            wait = wait.max(bucket.wait(bucket_config, cost));
+        }
+        if !wait.is_zero() {
+            return Throttle::Throttled {
+                retry_after: wait,
+                notify: self.notified.insert(sender.user_id),
+            };
+        }
+        for (key, bucket_config) in &keys {
+            if let Some(bucket) = self.buckets.get_mut(key) {
+                bucket.tokens -= limit.cost.min(bucket_config.capacity) as f64;
+            }
+        }
+        if limit.cooldown > 0 {
+            let ready = now + Duration::from_secs(limit.cooldown);
+            self.cooldowns
+                .insert((sender.user_id, limit.command), ready);
+        }
+        self.notified.remove(&sender.user_id);
+        Throttle::Allowed
+    }
+
+    /// Drop the buckets that are full again and the cooldowns that are over. A user left with
+    /// neither is told again when refused next time.
+    pub fn prune(&mut self, config: &RateLimitConfig, now: Instant) {
+        self.buckets.retain(|key, bucket| {
+            let bucket_config = key.config(config);
+            bucket_config.capacity > 0 && !bucket.is_full(bucket_config, now)
+        });
+        self.cooldowns.retain(|_, ready| *ready > now);
+        let (buckets, cooldowns) = (&self.buckets, &self.cooldowns);
+        self.notified.retain(|user_id| {
+            buckets.contains_key(&BucketKey::User(*user_id))
+                || cooldowns.keys().any(|(id, _)| id == user_id)
+        });
+    }
+}
+
+pub fn rate_limiter() -> &'static Mutex<RateLimiter> {
+    static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+    LIMITER.get_or_init(Default::default)
+}
+
+/// Check `limit` for `sender` against the configured limits.
+///
+/// `Err` holds the notice to answer with, or `None` to stay silent when `sender` was already told.
+pub fn check_limit(sender: Sender, limit: &CommandLimit) -> Result<(), Option<MessageChain>> {
+    let Ok(mut limiter) = rate_limiter().lock() else {
+        return Ok(());
+    };
+    match limiter.check(sender, limit, &config().rate_limit, Instant::now()) {
+        Throttle::Allowed => Ok(()),
+        Throttle::Throttled {
+            retry_after,
+            notify,
+        } => Err(notify.then(|| {
+            MessageChain::new().text(format!(
+                "操作太频繁啦，请 {} 秒后再试",
+                retry_after.as_secs().max(1)
+            ))
+        })),
+    }
+}
+
+#[test]
+fn test_rate_limiter() {
+    let config = RateLimitConfig {
+        user: BucketConfig {
+            capacity: 2,
+            per_minute: 6,
+        },
+        group: BucketConfig {
+            capacity: 0,
+            per_minute: 0,
+        },
+        global: BucketConfig {
+            capacity: 0,
+            per_minute: 0,
+        },
+    };
+    let price = CommandLimit {
+        command: "blueprint.price",
+        cost: 2,
+        cooldown: 0,
+    };
+    let status = CommandLimit {
+        command: "status",
+        cost: 1,
+        cooldown: 30,
+    };
+    let alice = Sender {
+        user_id: 1,
+        group_id: Some(10),
+    };
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut limiter = RateLimiter::default();
+
+    assert_eq!(
+        limiter.check(alice, &price, &config, at(0)),
+        Throttle::Allowed
+    );
+    assert_eq!(
+        limiter.check(alice, &price, &config, at(1)),
+        Throttle::Throttled {
+            retry_after: Duration::from_secs(19),
+            notify: true
+        }
+    );
+    assert!(matches!(
+        limiter.check(alice, &price, &config, at(2)),
+        Throttle::Throttled { notify: false, .. }
+    ));
+    // another user has a bucket of their own
+    let bob = Sender {
+        user_id: 2,
+        ..alice
+    };
+    assert_eq!(
+        limiter.check(bob, &price, &config, at(2)),
+        Throttle::Allowed
+    );
+
+    assert_eq!(
+        limiter.check(alice, &status, &config, at(20)),
+        Throttle::Allowed
+    );
+    assert!(matches!(
+        limiter.check(alice, &status, &config, at(40)),
+        Throttle::Throttled { retry_after, notify: true } if retry_after == Duration::from_secs(10)
+    ));
+    assert_eq!(
+        limiter.check(alice, &status, &config, at(50)),
+        Throttle::Allowed
+    );
+}
+
+#[test]
+fn test_rate_limiter_prune() {
+    let config = RateLimitConfig {
+        global: BucketConfig {
+            capacity: 0,
+            per_minute: 0,
+        },
+        ..Default::default()
+    };
+    let limit = CommandLimit {
+        command: "blueprint.price",
+        cost: 3,
+        cooldown: 10,
+    };
+    let start = Instant::now();
+    let mut limiter = RateLimiter::default();
+    for user_id in 0..PRUNE_INTERVAL as u64 - 1 {
+        let sender = Sender {
+            user_id,
+            group_id: Some(user_id),
+        };
+        assert_eq!(
+            limiter.check(sender, &limit, &config, start),
+            Throttle::Allowed
+        );
+    }
+    assert_eq!(limiter.cooldowns.len(), PRUNE_INTERVAL as usize - 1);
+    // the next check sweeps, once every bucket has refilled
+    let later = start + Duration::from_secs(60);
+    let sender = Sender {
+        user_id: 0,
+        group_id: None,
+    };
+    assert_eq!(
+        limiter.check(sender, &limit, &config, later),
+        Throttle::Allowed
+    );
+    assert_eq!(limiter.cooldowns.len(), 1);
+    assert_eq!(limiter.buckets.len(), 1);
+    assert!(limiter.buckets.contains_key(&BucketKey::User(0)));
+}
@@ -1,6 +1,16 @@
+mod account;
+mod context;
 pub mod distributor;
+mod limit;
+mod pending;
 mod syntax;
 mod traits;
+mod value;
 
+pub use account::*;
+pub use context::*;
+pub use limit::*;
+pub use pending::*;
 pub use syntax::*;
 pub use traits::*;
+pub use value::*;
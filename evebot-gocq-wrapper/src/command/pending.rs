@@ -0,0 +1,242 @@
+use crate::backend::{EveBackend, TypeItem};
+use crate::command::{CommandLimit, CommandOutput, Context, FollowUp, Sender};
+use crate::error::{BotError, BotErrorGroup, BotGroupResult};
+use crate::server::{MessageChain, Token};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long a user has to choose from the candidates of a search.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+tokio::task_local! {
+    static CHOSEN: TypeItem;
+    static RESOLVED: Rc<RefCell<Option<TypeItem>>>;
+}
+
+/// The type the user chose for the command being run, or the one it found when first run.
+/// `fetch_type!` takes it instead of searching again.
+pub fn chosen_type() -> Option<TypeItem> {
+    CHOSEN.try_with(Clone::clone).ok()
+}
+
+/// Run `f`, and return the type `fetch_type!` found while running it, see [`resolved`].
+pub async fn resolving<F: Future>(f: F) -> (F::Output, Option<TypeItem>) {
+    let slot = Rc::<RefCell<_>>::default();
+    let output = RESOLVED.scope(slot.clone(), f).await;
+    let item = slot.take();
+    (output, item)
+}
+
+/// Report `item` as the type found by the command being run, so that following up on its
+/// answer does not search again.
+pub fn resolved(item: &TypeItem) {
+    let _ = RESOLVED.try_with(|slot| *slot.borrow_mut() = Some(item.clone()));
+}
+
+/// `context`, run with `item` instead of searching for its type, if there is one.
+pub fn with_type(context: Context, item: Option<TypeItem>) -> Context {
+    match item {
+        Some(item) => Arc::new(Chosen {
+            inner: context,
+            item,
+        }),
+        None => context,
+    }
+}
+
+/// A command waiting for the user to choose one of `candidates`.
+pub struct Pending {
+    pub context: Context,
+    pub candidates: Vec<TypeItem>,
+}
+
+impl Pending {
+    /// Ask the user to choose, if the only error in `err` is a search with several candidates.
+    pub fn ask(context: Context, err: &BotErrorGroup) -> Option<CommandOutput> {
+        let [BotError::Ambiguous { candidates }] = err.errors() else {
+            return None;
+        };
+        let mut text = format!(
+            "找到多个物品，请在 {} 秒内回复序号：",
+            PENDING_TIMEOUT.as_secs()
+        );
+        for (i, item) in candidates.iter().enumerate() {
+            text.push_str(&format!("\n{}. {}", i + 1, item.type_name));
+        }
+        Some(CommandOutput {
+            message: MessageChain::new().text(text),
+            context: None,
+            pending: Some(Pending {
+                context,
+                candidates: candidates.clone(),
+            }),
+        })
+    }
+}
+
+/// The answer of running `context`, or a question if the user has to choose a type first.
+pub fn run_output(
+    context: Context,
+    result: BotGroupResult<MessageChain>,
+) -> BotGroupResult<CommandOutput> {
+    match result {
+        Ok(message) => Ok(CommandOutput {
+            message,
+            context: Some(context),
+            pending: None,
+        }),
+        Err(err) => Pending::ask(context, &err).ok_or(err),
+    }
+}
+
+/// A command run with the type the user chose for it, or the one it found before, also when
+/// followed up on.
+struct Chosen {
+    inner: Context,
+    item: TypeItem,
+}
+
+impl FollowUp for Chosen {
+    fn follow_up(&self, param: Vec<Token>) -> Option<BotGroupResult<Context>> {
+        Some(self.inner.follow_up(param)?.map(|inner| {
+            Arc::new(Chosen {
+                inner,
+                item: self.item.clone(),
+            }) as Context
+        }))
+    }
+
+    fn run<'a>(
+        &'a self,
+        backend: &'a dyn EveBackend,
+    ) -> Pin<Box<dyn Future<Output = BotGroupResult<MessageChain>> + 'a>> {
+        Box::pin(CHOSEN.scope(self.item.clone(), self.inner.run(backend)))
+    }
+
+    fn limit(&self) -> Option<CommandLimit> {
+        self.inner.limit()
+    }
+}
+
+/// Commands waiting for their sender to choose, keyed by the account and the sender, so each
+/// user of a group has their own. A user has at most one.
+#[derive(Default)]
+pub struct PendingStore {
+    pending: HashMap<(u64, Sender), (Instant, Pending)>,
+}
+
+impl PendingStore {
+    pub fn insert(&mut self, self_id: u64, sender: Sender, pending: Pending, now: Instant) {
+        self.pending
+            .retain(|_, (asked, _)| now.duration_since(*asked) < PENDING_TIMEOUT);
+        self.pending.insert((self_id, sender), (now, pending));
+    }
+
+    /// The command waiting for `sender`, to be run with candidate number `choice`, counted
+    /// from 1. Another number keeps it waiting.
+    pub fn choose(
+        &mut self,
+        self_id: u64,
+        sender: Sender,
+        choice: usize,
+        now: Instant,
+    ) -> Option<Context> {
+        let key = (self_id, sender);
+        let (asked, pending) = self.pending.get(&key)?;
+        if now.duration_since(*asked) >= PENDING_TIMEOUT {
+            self.pending.remove(&key);
+            return None;
+        }
+        let item = pending.candidates.get(choice.checked_sub(1)?)?.clone();
+        let (_, pending) = self.pending.remove(&key)?;
+        Some(Arc::new(Chosen {
+            inner: pending.context,
+            item,
+        }))
+    }
+}
+
+pub fn pending_store() -> &'static Mutex<PendingStore> {
+    static STORE: OnceLock<Mutex<PendingStore>> = OnceLock::new();
+    STORE.get_or_init(Default::default)
+}
+
+/// Wait for `sender`, talking to `self_id`, to choose for `pending`.
+pub fn save_pending(self_id: u64, sender: Sender, pending: Pending) {
+    if let Ok(mut store) = pending_store().lock() {
+        store.insert(self_id, sender, pending, Instant::now());
+    }
+}
+
+/// See [`PendingStore::choose`].
+pub fn take_choice(self_id: u64, sender: Sender, choice: usize) -> Option<Context> {
+    pending_store()
+        .lock()
+        .ok()?
+        .choose(self_id, sender, choice, Instant::now())
+}
+
+#[test]
+fn test_pending_store() {
+    use crate::backend::MockBackend;
+    struct Dummy;
+    impl FollowUp for Dummy {
+        fn follow_up(&self, _: Vec<Token>) -> Option<BotGroupResult<Context>> {
+            None
+        }
+        fn run<'a>(
+            &'a self,
+            _: &'a dyn EveBackend,
+        ) -> Pin<Box<dyn Future<Output = BotGroupResult<MessageChain>> + 'a>> {
+            Box::pin(async { Ok(MessageChain::new().text(chosen_type().unwrap().type_name)) })
+        }
+    }
+    let candidates = ["三钛合金", "三钛合金测试样品"]
+        .map(|name| TypeItem {
+            type_name: name.into(),
+            ..Default::default()
+        })
+        .to_vec();
+    let ask = || {
+        let err = BotErrorGroup::from(BotError::Ambiguous {
+            candidates: candidates.clone(),
+        });
+        run_output(Arc::new(Dummy), Err(err)).unwrap()
+    };
+    let output = ask();
+    assert!(output
+        .message
+        .to_string()
+        .ends_with("\n1. 三钛合金\n2. 三钛合金测试样品"));
+
+    let alice = Sender {
+        user_id: 1,
+        group_id: Some(10),
+    };
+    let bob = Sender {
+        user_id: 2,
+        ..alice
+    };
+    let start = Instant::now();
+    let mut store = PendingStore::default();
+    store.insert(1, alice, output.pending.unwrap(), start);
+    assert!(store.choose(1, bob, 2, start).is_none());
+    assert!(store.choose(1, alice, 3, start).is_none());
+    let context = store.choose(1, alice, 2, start).unwrap();
+    // answered once only
+    assert!(store.choose(1, alice, 2, start).is_none());
+    let message = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(context.run(&MockBackend::default()))
+        .unwrap();
+    assert_eq!(message.to_string(), "三钛合金测试样品");
+
+    store.insert(1, alice, ask().pending.unwrap(), start);
+    assert!(store.choose(1, alice, 1, start + PENDING_TIMEOUT).is_none());
+}
@@ -1,6 +1,11 @@
+use crate::command::{format_duration, format_isk};
+use crate::constant::eve::server::Server;
 use itertools::Itertools;
+use regex::Regex;
 use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ServiceSyntax<const PARAM_ARG_LEN: usize, const POS_ARG_LEN: usize> {
     pub title: &'static str,
@@ -30,7 +35,7 @@ impl<const PARAM_ARG_LEN: usize, const POSITIONAL_ARG_LEN: usize> Display
             } else {
                 self.positional_args
                     .iter()
-                    .map(|s| format!("[{}]", s.arg_name))
+                    .map(PositionalArg::tag)
                     .join(", ")
                     + " "
             },
@@ -61,6 +66,7 @@ impl<const PARAM_ARG_LEN: usize, const POSITIONAL_ARG_LEN: usize> Display
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ParamArg {
     pub alias: Option<&'static [&'static str]>,
@@ -68,6 +74,8 @@ pub struct ParamArg {
     pub arg_type: ArgType,
     pub default: Option<ArgValue>,
     pub description: &'static str,
+    pub constraint: Constraint,
+    pub follow_up: bool,
 }
 
 //noinspection DuplicatedCode
@@ -75,9 +83,11 @@ impl Display for ParamArg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{name: <20}Type: {arg_type}.{optional}{default}\n                    {description}{alias}",
+            "{name: <20}Type: {arg_type}.{optional}{default}{constraint}{follow_up}\n                    {description}{alias}{negation}",
             name = &self.arg_name,
             arg_type = &self.arg_type,
+            constraint = &self.constraint,
+            follow_up = if self.follow_up { " Follow-up." } else { "" },
             optional = if self.default.is_some() {
                 " Optional."
             } else {
@@ -93,16 +103,37 @@ impl Display for ParamArg {
                 format!("\n                    Alias: {}", val.join(", "))
             } else {
                 "".to_string()
+            },
+            negation = if let ArgType::Flag = self.arg_type {
+                format!("\n                    Turn off: no-{}", self.arg_name)
+            } else {
+                "".to_string()
             }
         )
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct PositionalArg {
     pub arg_name: &'static str,
     pub arg_type: ArgType,
     pub description: &'static str,
+    /// Takes the words up to the first parameter key as one text.
+    pub greedy: bool,
+    /// Takes every item up to the first parameter key.
+    pub variadic: bool,
+    pub constraint: Constraint,
+}
+
+impl PositionalArg {
+    fn tag(&self) -> String {
+        if self.greedy || self.variadic {
+            format!("[{}...]", self.arg_name)
+        } else {
+            format!("[{}]", self.arg_name)
+        }
+    }
 }
 
 //noinspection DuplicatedCode
@@ -110,14 +141,98 @@ impl Display for PositionalArg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{name: <20}Type: {arg_type}.\n                    {description}",
+            "{name: <20}Type: {arg_type}.{rest}{constraint}\n                    {description}",
             name = self.arg_name,
             arg_type = self.arg_type,
+            rest = if self.greedy {
+                " Greedy."
+            } else if self.variadic {
+                " Variadic."
+            } else {
+                ""
+            },
+            constraint = self.constraint,
             description = self.description,
         )
     }
 }
 
+/// Value constraints declared in the syntax file, checked by the generated parser.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Constraint {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub regex: Option<&'static str>,
+    pub max_length: Option<usize>,
+    pub non_empty: bool,
+}
+
+impl Constraint {
+    fn range(&self) -> String {
+        fn bound(v: f64) -> String {
+            if v.fract() == 0.0 && v.abs() < 1e15 {
+                format!("{}", v as i64)
+            } else {
+                format!("{}", v)
+            }
+        }
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("[{}, {}]", bound(min), bound(max)),
+            (Some(min), None) => format!("[{}, ...)", bound(min)),
+            (None, Some(max)) => format!("(..., {}]", bound(max)),
+            (None, None) => "(..., ...)".into(),
+        }
+    }
+
+    pub fn check_number(&self, value: f64) -> Result<(), String> {
+        if self.min.is_some_and(|min| value < min) || self.max.is_some_and(|max| value > max) {
+            Err(format!("取值超出范围 {}", self.range()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `regex` keeps `self.regex` once compiled. `create_syntax` emits one for each constraint.
+    pub fn check_text(&self, value: &str, regex: &OnceLock<Regex>) -> Result<(), String> {
+        if self.non_empty && value.trim().is_empty() {
+            return Err("参数不能为空".into());
+        }
+        if let Some(len) = self.max_length {
+            if value.chars().count() > len {
+                return Err(format!("长度不能超过 {} 个字符", len));
+            }
+        }
+        if let Some(pattern) = self.regex {
+            // validated when the syntax file was compiled
+            let regex = regex.get_or_init(|| Regex::new(pattern).expect("invalid regex"));
+            if !regex.is_match(value) {
+                return Err(format!("不符合格式 {}", pattern));
+            }
+        }
+        Ok(())
+    }
+}
+
+//noinspection DuplicatedCode
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.min.is_some() || self.max.is_some() {
+            write!(f, " Range: {}.", self.range())?;
+        }
+        if self.non_empty {
+            write!(f, " Non-empty.")?;
+        }
+        if let Some(len) = self.max_length {
+            write!(f, " Max length: {}.", len)?;
+        }
+        if let Some(regex) = self.regex {
+            write!(f, " Pattern: {}.", regex)?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum ArgType {
     AnyText,
@@ -125,6 +240,17 @@ pub enum ArgType {
     Float,
     Int,
     Boolean,
+    /// An ISK amount, e.g. `1.5b` or `5000万`.
+    Isk,
+    /// e.g. `2h30m` or `3d`.
+    Duration,
+    /// Elements separated by commas or whitespace within a single parameter.
+    List(&'static ArgType),
+    /// An `@` of a group member.
+    Mention,
+    Server,
+    /// A key without a value, `no-<key>` turns it off.
+    Flag,
 }
 
 //noinspection DuplicatedCode
@@ -136,6 +262,12 @@ impl Display for ArgType {
             Self::Float => write!(f, "FLOAT"),
             Self::Int => write!(f, "INT"),
             Self::Boolean => write!(f, "BOOLEAN"),
+            Self::Isk => write!(f, "ISK"),
+            Self::Duration => write!(f, "DURATION"),
+            Self::List(t) => write!(f, "LIST<{}>", t),
+            Self::Mention => write!(f, "MENTION"),
+            Self::Server => write!(f, "SERVER"),
+            Self::Flag => write!(f, "FLAG"),
         }
     }
 }
@@ -148,6 +280,10 @@ pub enum ArgValue {
     Float(f64),
     Int(i64),
     Boolean(bool),
+    Isk(f64),
+    Duration(std::time::Duration),
+    List(&'static [ArgValue]),
+    Server(Server),
 }
 
 //noinspection DuplicatedCode
@@ -159,6 +295,38 @@ impl Display for ArgValue {
             Self::Float(val) => write!(f, "{:?}", val),
             Self::Int(val) => write!(f, "{:?}", val),
             Self::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Self::Isk(val) => write!(f, "{}", format_isk(*val)),
+            Self::Duration(val) => write!(f, "{}", format_duration(*val)),
+            Self::List(val) => write!(f, "{}", val.iter().join(",")),
+            Self::Server(val) => write!(f, "{}", val.as_api_like()),
         }
     }
 }
+
+#[test]
+fn test_constraint() {
+    let level = Constraint {
+        min: Some(0.0),
+        max: Some(10.0),
+        ..Default::default()
+    };
+    assert!(level.check_number(10.0).is_ok());
+    assert_eq!(
+        level.check_number(11.0).unwrap_err(),
+        "取值超出范围 [0, 10]"
+    );
+    assert_eq!(level.to_string(), " Range: [0, 10].");
+
+    let name = Constraint {
+        regex: Some("^[a-z]+$"),
+        max_length: Some(4),
+        non_empty: true,
+        ..Default::default()
+    };
+    let regex = OnceLock::new();
+    assert!(name.check_text("abc", &regex).is_ok());
+    assert!(name.check_text("  ", &regex).is_err());
+    assert!(name.check_text("abcde", &regex).is_err());
+    assert!(name.check_text("ab1", &regex).is_err());
+    assert!(regex.get().is_some());
+}
@@ -1,24 +1,27 @@
 use crate::command::syntax::ServiceSyntax;
 use crate::error::BotGroupResult;
-use crate::server::ParamItem;
+use crate::server::Token;
 
 /// # Special Agreement
 ///
 /// All structures implemented this should also implement an asynchronous method called `get_content`.
+/// It reaches the EVE service only through `backend`, so tests can pass a `MockBackend`.
 ///
 /// The signature should be:
 ///
 /// ```
-/// async fn get_content(&self) -> BotGroupResult<impl serde::Serialize> {
+/// async fn get_content(&self, backend: &dyn EveBackend) -> BotGroupResult<MessageChain> {
 ///     ...
 /// }
 /// ```
 pub trait BotService<const PARAM_ARG_LEN: usize, const POS_ARG_LEN: usize> {
     type RESULT;
+    #[allow(dead_code)]
     const SYNTAX: ServiceSyntax<PARAM_ARG_LEN, POS_ARG_LEN>;
     const SYNTAX_TEXT: &'static str = "";
 
-    fn get_result<T>(param: std::iter::Peekable<T>) -> BotGroupResult<Self::RESULT>
+    fn get_result<T, I>(param: std::iter::Peekable<T>) -> BotGroupResult<Self::RESULT>
     where
-        T: Iterator<Item = ParamItem>;
+        T: Iterator<Item = I>,
+        I: Into<Token>;
 }
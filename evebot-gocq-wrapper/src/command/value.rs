@@ -0,0 +1,212 @@
+//! Text forms of the argument types that need more than `str::parse`.
+//!
+//! These are called from the parsers generated by `create_syntax`, only when a syntax file
+//! declares their type, hence the `dead_code` allows.
+
+use crate::server::{ParamItem, Token};
+use std::iter::Peekable;
+use std::time::Duration;
+
+/// Parse an ISK amount such as `1.5b`, `300m`, `20k`, `3亿` or `5000万`.
+///
+/// A trailing `isk` is ignored and suffixes are case-insensitive.
+#[allow(dead_code)]
+pub fn parse_isk(text: &str) -> Option<f64> {
+    let text = text.trim().to_lowercase();
+    let text = text.strip_suffix("isk").unwrap_or(&text).trim_end();
+    let (number, unit) = match text.char_indices().last()? {
+        (idx, 'k' | '千') => (&text[..idx], 1e3),
+        (idx, '万' | 'w') => (&text[..idx], 1e4),
+        (idx, 'm') => (&text[..idx], 1e6),
+        (idx, '亿') => (&text[..idx], 1e8),
+        (idx, 'b') => (&text[..idx], 1e9),
+        (idx, 't') => (&text[..idx], 1e12),
+        _ => (text, 1.0),
+    };
+    let value = number.trim_end().replace('_', "").parse::<f64>().ok()? * unit;
+    value.is_finite().then_some(value)
+}
+
+/// Parse a duration made of `<number><unit>` parts, e.g. `2h30m`, `3d` or `1.5h`.
+///
+/// Units are `d`, `h`, `m`, `s` and their Chinese counterparts. A bare number is rejected
+/// to avoid guessing the unit.
+#[allow(dead_code)]
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let mut rest = text.trim();
+    let mut seconds = 0.0;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter(|&idx| idx > 0)?;
+        let number = rest[..number_end].parse::<f64>().ok()?;
+        rest = &rest[number_end..];
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = match rest[..unit_end].to_lowercase().as_str() {
+            "d" | "天" => 86400.0,
+            "h" | "小时" | "时" => 3600.0,
+            "m" | "min" | "分钟" | "分" => 60.0,
+            "s" | "秒" => 1.0,
+            _ => return None,
+        };
+        rest = &rest[unit_end..];
+        seconds += number * unit;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Join `first` and the values right after it, up to the next key as told by `is_key`, into the
+/// text of a `List`, so that `levels 1 2 3` reads as `levels "1 2 3"`.
+#[allow(dead_code)]
+pub fn join_list<I: Iterator<Item = Token>>(
+    mut first: Token,
+    param: &mut Peekable<I>,
+    is_key: impl Fn(&str) -> bool,
+) -> Token {
+    let ParamItem::Text(text) = &mut first.item else {
+        return first;
+    };
+    while let Some(next) =
+        param.next_if(|v| matches!(&v.item, ParamItem::Text(t) if !is_key(t.as_str())))
+    {
+        if let ParamItem::Text(t) = next.item {
+            text.push(' ');
+            text.push_str(&t);
+        }
+        first.span = match (first.span, next.span) {
+            (Some(span), Some(next)) => Some(span.to(next)),
+            (span, _) => span,
+        };
+    }
+    first
+}
+
+/// Format an ISK amount with the largest suffix of `parse_isk` that keeps it readable.
+pub fn format_isk(value: f64) -> String {
+    let (value, unit) = match value.abs() {
+        v if v >= 1e9 => (value / 1e9, "b"),
+        v if v >= 1e6 => (value / 1e6, "m"),
+        v if v >= 1e3 => (value / 1e3, "k"),
+        _ => (value, ""),
+    };
+    format!("{}{}", (value * 100.0).round() / 100.0, unit)
+}
+
+/// Format a duration in the form accepted by [`parse_duration`].
+pub fn format_duration(value: Duration) -> String {
+    let mut seconds = value.as_secs();
+    if seconds == 0 {
+        return "0s".into();
+    }
+    let mut text = String::new();
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if seconds >= size {
+            text += &format!("{}{}", seconds / size, unit);
+            seconds %= size;
+        }
+    }
+    text
+}
+
+#[test]
+fn test_parse_value() {
+    assert_eq!(parse_isk("1.5b"), Some(1.5e9));
+    assert_eq!(parse_isk("300M"), Some(3e8));
+    assert_eq!(parse_isk("20k"), Some(2e4));
+    assert_eq!(parse_isk("3亿"), Some(3e8));
+    assert_eq!(parse_isk("5000万"), Some(5e7));
+    assert_eq!(parse_isk("1000 isk"), Some(1000.0));
+    assert_eq!(parse_isk("b"), None);
+    assert_eq!(parse_isk("1.5x"), None);
+    assert_eq!(format_isk(1.5e9), "1.5b");
+
+    assert_eq!(parse_duration("2h30m"), Some(Duration::from_secs(9000)));
+    assert_eq!(parse_duration("3d"), Some(Duration::from_secs(259200)));
+    assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("1天2小时"), Some(Duration::from_secs(93600)));
+    assert_eq!(parse_duration("90"), None);
+    assert_eq!(parse_duration("h"), None);
+    assert_eq!(format_duration(Duration::from_secs(9000)), "2h30m");
+}
+
+#[cfg(test)]
+#[evebot_proc_macro::create_syntax("syntax/test/arg_types.json")]
+struct ArgTypes;
+
+/// Every syntax is a command, to be run by its `FollowUp` impl.
+#[cfg(test)]
+impl ArgTypes {
+    async fn get_content(
+        &self,
+        _backend: &dyn crate::backend::EveBackend,
+    ) -> crate::error::BotGroupResult<crate::server::MessageChain> {
+        Ok(crate::server::MessageChain::new())
+    }
+}
+
+#[test]
+fn test_parse_arg_types() {
+    use crate::command::BotService;
+    use crate::constant::eve::server::Server;
+    use crate::server::ParamItem;
+
+    let parse = |items: Vec<ParamItem>| ArgTypes::parse(items.into_iter().peekable());
+    let text = |s: &str| ParamItem::Text(s.into());
+
+    let res = parse(vec![ParamItem::At(42), ParamItem::At(43)]).unwrap();
+    assert_eq!(res.targets, vec![42, 43]);
+    assert_eq!(res.budget, 1e6);
+    assert_eq!(res.duration, Duration::from_secs(3600));
+    assert_eq!(res.levels, vec![1, 2]);
+    assert_eq!(res.server, Server::Tranquility);
+
+    let res = parse(vec![
+        ParamItem::At(42),
+        text("budget"),
+        text("3亿"),
+        text("duration"),
+        text("2h30m"),
+        text("levels"),
+        text("3,4 5"),
+        text("server"),
+        text("se"),
+    ])
+    .unwrap();
+    assert_eq!(res.budget, 3e8);
+    assert_eq!(res.duration, Duration::from_secs(9000));
+    assert_eq!(res.levels, vec![3, 4, 5]);
+    assert_eq!(res.server, Server::Serenity);
+
+    // the elements of a list need no quotes, up to the next key
+    let res = parse(vec![
+        ParamItem::At(42),
+        text("levels"),
+        text("1"),
+        text("2,3"),
+        text("4"),
+        text("count"),
+        text("5"),
+    ])
+    .unwrap();
+    assert_eq!(res.levels, vec![1, 2, 3, 4]);
+    assert_eq!(res.count, 5);
+
+    let err = parse(vec![
+        text("someone"),
+        text("count"),
+        ParamItem::At(42),
+        text("levels"),
+        text("1,x"),
+        text("budget"),
+        text("-1m"),
+    ])
+    .unwrap_err();
+    assert_eq!(err.as_ref().len(), 4);
+    assert!(parse(vec![text("budget"), text("1b")]).is_err());
+    assert!(ArgTypes::SYNTAX_TEXT.contains("Type: LIST<INT>. Optional. Default: 1,2."));
+}
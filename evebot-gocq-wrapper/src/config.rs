@@ -0,0 +1,634 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Default configuration file, looked up in the working directory when `--config` is absent.
+pub const DEFAULT_CONFIG_FILE: &str = "evebot.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The shared configuration handle.
+///
+/// Falls back to [`Config::default`] if [`init`] was never called, which is what tests rely on.
+/// Their images are stored in the temporary directory instead.
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        #[allow(unused_mut)]
+        let mut config = Config::default();
+        #[cfg(test)]
+        {
+            config.bot.image_directory = std::env::temp_dir().join("evebot-test-images");
+        }
+        config
+    })
+}
+
+/// Install the configuration for the whole process. Only the first call has any effect.
+pub fn init(config: Config) -> &'static Config {
+    let _ = CONFIG.set(config);
+    self::config()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub backend: BackendConfig,
+    pub bot: BotConfig,
+    pub timeout: TimeoutConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cache: CacheConfig,
+    pub policy: PolicyConfig,
+    /// Per-account settings, see [`Config::account`].
+    pub accounts: Vec<AccountConfig>,
+    /// Per-group settings, by `group_id`.
+    pub groups: Vec<GroupConfig>,
+    /// Short forms of commands, e.g. `jita = "eve market jita"`. The prefix is optional.
+    pub aliases: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Expected in `Authorization: Bearer <token>` of every event, and sent along with every
+    /// call to the HTTP API. Empty to accept anything.
+    pub access_token: String,
+    /// Key of the HMAC-SHA1 in `X-Signature` of events posted over HTTP. Empty to skip the check.
+    pub secret: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".into(),
+            port: 8080,
+            access_token: String::new(),
+            secret: String::new(),
+        }
+    }
+}
+
+/// The local EVE service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    /// How many times a GET is tried again after failing to connect or a 5xx answer.
+    pub retries: u32,
+    pub concurrency: ConcurrencyConfig,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            scheme: "http".into(),
+            host: "localhost".into(),
+            port: 8000,
+            retries: 2,
+            concurrency: ConcurrencyConfig::default(),
+        }
+    }
+}
+
+/// How many requests to each class of backend endpoint may be in flight at once.
+/// Requests over the limit wait for their turn, within the backend timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Type searches and lookups.
+    pub search: usize,
+    /// Market prices, including those of blueprint materials.
+    pub price: usize,
+    /// Rendered images, by far the slowest.
+    pub render: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            search: 8,
+            price: 8,
+            render: 2,
+        }
+    }
+}
+
+impl BackendConfig {
+    #[inline]
+    pub fn base_url(&self) -> String {
+        format!("{}://{}:{}", self.scheme, self.host, self.port)
+    }
+
+    /// `path` is expected to start with a `/`.
+    #[inline]
+    pub fn url(&self, path: impl AsRef<str>) -> String {
+        format!("{}{}", self.base_url(), path.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub prefixes: Vec<String>,
+    /// Where rendered images are stored. go-cqhttp must be able to read from here.
+    pub image_directory: PathBuf,
+    /// go-cqhttp's HTTP API, e.g. `http://127.0.0.1:5700`. Empty if there is none.
+    ///
+    /// Only needed over HTTP, to act on events that cannot be answered with a quick operation,
+    /// or to learn the message id of an answer that can be followed up on.
+    pub api_url: String,
+    /// Users allowed to run the commands marked `admin` in `distributor.json`.
+    pub admins: Vec<u64>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            prefixes: vec!["eve".into()],
+            image_directory: PathBuf::from("./images/"),
+            api_url: String::new(),
+            admins: Vec::new(),
+        }
+    }
+}
+
+impl BotConfig {
+    pub fn image_path(&self, file_name: impl AsRef<str>) -> PathBuf {
+        self.image_directory
+            .join(format!("{}.png", file_name.as_ref()))
+    }
+
+    /// The `file://` URI go-cqhttp expects in an image segment.
+    pub fn image_uri(&self, file_name: impl AsRef<str>) -> String {
+        let path = self.image_path(file_name);
+        let path = std::path::absolute(&path).unwrap_or(path);
+        let path = path.to_string_lossy().replace('\\', "/");
+        if path.starts_with('/') {
+            format!("file://{}", path)
+        } else {
+            format!("file:///{}", path)
+        }
+    }
+}
+
+/// Settings of one QQ account. Those left out are taken from `[bot]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountConfig {
+    pub self_id: u64,
+    pub prefixes: Option<Vec<String>>,
+    /// Top-level commands the account answers, e.g. `["market", "status"]`. Empty for all.
+    pub groups: Vec<String>,
+    /// Values of parameters left out of a command, e.g. `server = "tq"`, instead of the defaults
+    /// of its syntax.
+    pub defaults: BTreeMap<String, String>,
+    pub api_url: Option<String>,
+}
+
+/// The settings in effect for one QQ account, see [`Config::account`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+    /// Used to tell whether an `@` targets the bot.
+    pub self_id: u64,
+    pub prefixes: Vec<String>,
+    pub groups: Vec<String>,
+    pub defaults: BTreeMap<String, String>,
+    pub api_url: String,
+}
+
+impl Account {
+    #[inline]
+    pub fn is_prefix(&self, text: &str) -> bool {
+        self.prefixes.iter().any(|p| p == text)
+    }
+
+    /// The prefix `text` starts with, if it is glued to a command as in `.jita`.
+    ///
+    /// Only prefixes without letters or digits, such as `/` or `.`, can be glued.
+    pub fn glued_prefix<'a>(&'a self, text: &str) -> Option<&'a str> {
+        self.prefixes
+            .iter()
+            .filter(|p| !p.is_empty() && !p.chars().any(char::is_alphanumeric))
+            .find(|p| text.len() > p.len() && text.starts_with(p.as_str()))
+            .map(String::as_str)
+    }
+
+    /// Whether the top-level command `ident` is enabled.
+    #[inline]
+    pub fn group_enabled(&self, ident: &str) -> bool {
+        self.groups.is_empty() || self.groups.iter().any(|g| g == ident)
+    }
+}
+
+/// Settings of one QQ group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    pub group_id: u64,
+    /// Commands or aliases answered without a prefix, e.g. `["jita", "status"]`.
+    /// Empty to require one.
+    pub prefixless: Vec<String>,
+    /// Whether members may search with `pattern manual`, which is forwarded to the backend
+    /// as a SQL `LIKE` pattern.
+    pub manual_pattern: bool,
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        Self {
+            group_id: 0,
+            prefixless: vec![],
+            manual_pattern: true,
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` tokens, refilled by `per_minute` tokens a minute.
+/// A command takes its cost from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BucketConfig {
+    /// `0` for no limit.
+    pub capacity: u32,
+    pub per_minute: u32,
+}
+
+/// Limits on how many commands are run, on top of the cooldowns in `distributor.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// For each user.
+    pub user: BucketConfig,
+    /// For each group, shared by its members.
+    pub group: BucketConfig,
+    /// Shared by everyone.
+    pub global: BucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            user: BucketConfig {
+                capacity: 5,
+                per_minute: 5,
+            },
+            group: BucketConfig {
+                capacity: 20,
+                per_minute: 20,
+            },
+            global: BucketConfig {
+                capacity: 60,
+                per_minute: 60,
+            },
+        }
+    }
+}
+
+/// Keeps up to `capacity` answers of the backend for `ttl` seconds each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKindConfig {
+    pub ttl: u64,
+    /// `0` to keep nothing.
+    pub capacity: usize,
+}
+
+impl CacheKindConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl)
+    }
+}
+
+/// The answers of the backend kept in memory, by kind. Rendered images are never kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Types found by name or id, which only change with the game.
+    pub types: CacheKindConfig,
+    /// Market prices, of single types and of blueprint materials.
+    pub prices: CacheKindConfig,
+    /// The materials of blueprints.
+    pub materials: CacheKindConfig,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            types: CacheKindConfig {
+                ttl: 86400,
+                capacity: 4096,
+            },
+            prices: CacheKindConfig {
+                ttl: 60,
+                capacity: 1024,
+            },
+            materials: CacheKindConfig {
+                ttl: 3600,
+                capacity: 256,
+            },
+        }
+    }
+}
+
+/// How the bot answers requests and notices.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Accept every friend request.
+    pub accept_friend: bool,
+    /// Accept every invitation to a group.
+    pub accept_group_invite: bool,
+    /// Sent when someone joins a group, with `{user}` replaced by an `@` of them.
+    /// Empty to stay silent.
+    pub greeting: String,
+}
+
+/// All values are in seconds.
+///
+/// `backend` bounds a whole request to the backend, reading the answer included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    pub connect: u64,
+    pub backend: u64,
+    pub action: u64,
+    /// The heartbeat interval of an account that has not reported its own.
+    pub heartbeat: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect: 5,
+            backend: 60,
+            action: 30,
+            heartbeat: 5,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    #[inline]
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect)
+    }
+
+    #[inline]
+    pub fn backend(&self) -> Duration {
+        Duration::from_secs(self.backend)
+    }
+
+    #[inline]
+    pub fn action(&self) -> Duration {
+        Duration::from_secs(self.action)
+    }
+
+    #[inline]
+    pub fn heartbeat(&self) -> Duration {
+        Duration::from_secs(self.heartbeat)
+    }
+}
+
+impl Config {
+    /// Load the configuration file and apply `EVEBOT_*` environment overrides.
+    ///
+    /// Without an explicit path, [`DEFAULT_CONFIG_FILE`] is used if it exists,
+    /// and the defaults otherwise.
+    pub fn load(path: Option<&Path>) -> std::io::Result<Self> {
+        let path = match path {
+            Some(p) => Some(p.to_owned()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()),
+        };
+        let mut config = match path {
+            Some(path) => Self::from_toml(&std::fs::read_to_string(&path)?).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?,
+            None => Self::default(),
+        };
+        config
+            .apply_env(|key| std::env::var(key).ok())
+            .and_then(|()| config.validate())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        Ok(config)
+    }
+
+    /// Reject the values that parse but cannot work.
+    pub fn validate(&self) -> Result<(), String> {
+        let concurrency = &self.backend.concurrency;
+        for (key, value) in [
+            ("search", concurrency.search),
+            ("price", concurrency.price),
+            ("render", concurrency.render),
+        ] {
+            // no request of the class could ever be sent
+            if value == 0 {
+                return Err(format!("backend.concurrency.{} must be at least 1", key));
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    #[inline]
+    pub fn group(&self, group_id: u64) -> Option<&GroupConfig> {
+        self.groups.iter().find(|g| g.group_id == group_id)
+    }
+
+    /// The settings of the account `self_id`, which need not be listed in `[[accounts]]`.
+    pub fn account(&self, self_id: u64) -> Account {
+        let account = self.accounts.iter().find(|a| a.self_id == self_id);
+        Account {
+            self_id,
+            prefixes: account
+                .and_then(|a| a.prefixes.clone())
+                .unwrap_or_else(|| self.bot.prefixes.clone()),
+            groups: account.map(|a| a.groups.clone()).unwrap_or_default(),
+            defaults: account.map(|a| a.defaults.clone()).unwrap_or_default(),
+            api_url: account
+                .and_then(|a| a.api_url.clone())
+                .unwrap_or_else(|| self.bot.api_url.clone()),
+        }
+    }
+
+    /// Override values from the environment. `env` returns the value of a variable, if set.
+    pub fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        fn parse<T: std::str::FromStr>(key: &str, value: String) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value of {}: {:?}", key, value))
+        }
+
+        macro_rules! override_with {
+            ($($key: literal => $field: expr),+ $(,)?) => {
+                $(if let Some(value) = env($key) {
+                    $field = parse($key, value)?;
+                })+
+            };
+        }
+
+        override_with! {
+            "EVEBOT_HOST" => self.server.host,
+            "EVEBOT_PORT" => self.server.port,
+            "EVEBOT_ACCESS_TOKEN" => self.server.access_token,
+            "EVEBOT_SECRET" => self.server.secret,
+            "EVEBOT_BACKEND_SCHEME" => self.backend.scheme,
+            "EVEBOT_BACKEND_HOST" => self.backend.host,
+            "EVEBOT_BACKEND_PORT" => self.backend.port,
+            "EVEBOT_BACKEND_RETRIES" => self.backend.retries,
+            "EVEBOT_IMAGE_DIRECTORY" => self.bot.image_directory,
+            "EVEBOT_API_URL" => self.bot.api_url,
+            "EVEBOT_TIMEOUT_CONNECT" => self.timeout.connect,
+            "EVEBOT_TIMEOUT_BACKEND" => self.timeout.backend,
+            "EVEBOT_TIMEOUT_ACTION" => self.timeout.action,
+            "EVEBOT_TIMEOUT_HEARTBEAT" => self.timeout.heartbeat,
+            "EVEBOT_ACCEPT_FRIEND" => self.policy.accept_friend,
+            "EVEBOT_ACCEPT_GROUP_INVITE" => self.policy.accept_group_invite,
+            "EVEBOT_GREETING" => self.policy.greeting,
+        }
+        if let Some(value) = env("EVEBOT_PREFIXES") {
+            self.bot.prefixes = value
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(ToOwned::to_owned)
+                .collect();
+        }
+        Ok(())
+    }
+}
+
+/// Find the value of `--config <path>` or `--config=<path>` in the command line arguments.
+pub fn config_path_from_args(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_load_config() {
+    let mut config = Config::from_toml(
+        r#"
+        [server]
+        port = 9090
+
+        [backend]
+        host = "eve-service"
+
+        [backend.concurrency]
+        render = 1
+
+        [bot]
+        prefixes = ["eve", "/"]
+        image_directory = "/srv/go-cqhttp/data/images/"
+
+        [[accounts]]
+        self_id = 123456
+        prefixes = ["吉他", "."]
+        groups = ["market"]
+        defaults = { server = "tq" }
+
+        [[groups]]
+        group_id = 1000
+        prefixless = ["jita"]
+        manual_pattern = false
+
+        [aliases]
+        jita = "eve market jita"
+
+        [rate_limit]
+        user = { capacity = 3, per_minute = 2 }
+
+        [cache]
+        prices = { ttl = 30, capacity = 0 }
+
+        [policy]
+        accept_friend = true
+        greeting = "欢迎 {user}"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.server.host, "127.0.0.1");
+    assert_eq!(config.server.port, 9090);
+    assert_eq!(
+        config.backend.url("/types/34/"),
+        "http://eve-service:8000/types/34/"
+    );
+    assert_eq!(config.backend.retries, 2);
+    assert_eq!(
+        (
+            config.backend.concurrency.search,
+            config.backend.concurrency.render
+        ),
+        (8, 1)
+    );
+    assert_eq!(config.bot.prefixes, vec!["eve", "/"]);
+    assert_eq!(
+        config.bot.image_uri("abc"),
+        "file:///srv/go-cqhttp/data/images/abc.png"
+    );
+    assert_eq!(config.timeout, TimeoutConfig::default());
+    assert_eq!(config.rate_limit.user.per_minute, 2);
+    assert_eq!(config.rate_limit.group, RateLimitConfig::default().group);
+    assert_eq!(config.cache.prices.ttl(), Duration::from_secs(30));
+    assert_eq!(config.cache.types, CacheConfig::default().types);
+    assert!(config.policy.accept_friend && !config.policy.accept_group_invite);
+    let account = config.account(123456);
+    assert!(account.is_prefix("吉他") && !account.is_prefix("eve"));
+    assert_eq!(account.glued_prefix(".jita"), Some("."));
+    assert_eq!(account.glued_prefix("吉他jita"), None);
+    assert_eq!(account.glued_prefix("."), None);
+    assert!(account.group_enabled("market") && !account.group_enabled("blueprint"));
+    assert_eq!(account.defaults["server"], "tq");
+    assert_eq!(config.group(1000).unwrap().prefixless, vec!["jita"]);
+    assert!(!config.group(1000).unwrap().manual_pattern);
+    assert_eq!(config.aliases["jita"], "eve market jita");
+    let other = config.account(654321);
+    assert!(other.is_prefix("eve") && other.group_enabled("blueprint"));
+
+    config
+        .apply_env(|key| match key {
+            "EVEBOT_BACKEND_PORT" => Some("8001".into()),
+            "EVEBOT_PREFIXES" => Some("eve, .".into()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(config.backend.port, 8001);
+    assert_eq!(config.bot.prefixes, vec!["eve", "."]);
+    assert!(config
+        .apply_env(|key| (key == "EVEBOT_PORT").then(|| "http".to_string()))
+        .is_err());
+
+    assert!(config.validate().is_ok());
+    config.backend.concurrency.render = 0;
+    assert_eq!(
+        config.validate().unwrap_err(),
+        "backend.concurrency.render must be at least 1"
+    );
+
+    assert_eq!(
+        config_path_from_args(["evebot", "--config", "a.toml"].map(String::from)),
+        Some(PathBuf::from("a.toml"))
+    );
+    assert_eq!(
+        config_path_from_args(["evebot", "--config=b.toml"].map(String::from)),
+        Some(PathBuf::from("b.toml"))
+    );
+    assert_eq!(config_path_from_args(["evebot"].map(String::from)), None);
+}
@@ -1,7 +1,7 @@
 use crate::error::BotError;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Server {
     Tranquility,
     #[default]
@@ -17,6 +17,7 @@ impl Server {
                 found: Some(s.as_ref().into()),
                 expected: Some("tq/trans/tranquility / se/seren/serenity".into()),
                 note: Some("不合法的服务器类型".into()),
+                span: None,
             }),
         }
     }
@@ -1,4 +1,7 @@
+use crate::backend::TypeItem;
+use crate::server::Span;
 use std::fmt::Formatter;
+use unicode_width::UnicodeWidthStr;
 
 pub type BotResult<T> = Result<T, BotError>;
 pub type BotGroupResult<T> = Result<T, BotErrorGroup>;
@@ -16,7 +19,10 @@ impl std::fmt::Display for BotErrorGroup {
             match err {
                 BotError::Syntax { .. } => syntax.push(err),
                 BotError::Backend { .. } => backend.push(err),
+                BotError::Timeout => backend.push(err),
+                BotError::Action { .. } => backend.push(err),
                 BotError::FileSystem { .. } => syntax.push(err),
+                BotError::Ambiguous { .. } => syntax.push(err),
             }
         }
         let syntax_text = if syntax.is_empty() {
@@ -111,6 +117,21 @@ impl BotErrorGroup {
     pub fn into_inner(self) -> Vec<BotError> {
         self.errors
     }
+
+    /// Like `Display`, but syntax errors with a span echo the command with a marker under the
+    /// offending part. `source` is the text of each message segment.
+    pub fn render(&self, source: &[String]) -> String {
+        let marked = self
+            .errors
+            .iter()
+            .filter_map(|err| err.marker(source))
+            .collect::<Vec<_>>();
+        if marked.is_empty() {
+            self.to_string()
+        } else {
+            format!("{}\n----------\n{}", self, marked.join("\n"))
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -119,18 +140,84 @@ pub enum BotError {
         found: Option<String>,
         expected: Option<String>,
         note: Option<String>,
+        /// Where in the message the error was found, if known.
+        span: Option<Span>,
     },
     Backend {
         code: Option<reqwest::StatusCode>,
         source: String,
     },
+    /// The backend took too long, or too many requests were waiting for it.
+    Timeout,
+    /// A search matched several types, see [`crate::backend::pick_type`].
+    Ambiguous {
+        candidates: Vec<TypeItem>,
+    },
     FileSystem {
         content: String,
     },
+    Action {
+        retcode: Option<i64>,
+        source: String,
+    },
+}
+
+impl BotError {
+    /// The `source` line and a `^^^` line under the span of a syntax error.
+    ///
+    /// Returns `None` for other errors and for spans outside `source`.
+    pub fn marker(&self, source: &[String]) -> Option<String> {
+        let Self::Syntax {
+            span: Some(span), ..
+        } = self
+        else {
+            return None;
+        };
+        let segment = source.get(span.segment)?;
+        let prefix = source[..span.segment].concat();
+        let before = segment.chars().take(span.start).collect::<String>();
+        let marked = segment
+            .chars()
+            .skip(span.start)
+            .take(span.end.saturating_sub(span.start))
+            .collect::<String>();
+        let line = source.concat();
+        Some(format!(
+            "{}\n{}{}",
+            line.trim_end(),
+            " ".repeat(prefix.width() + before.width()),
+            "^".repeat(marked.width().max(1))
+        ))
+    }
+}
+
+#[test]
+fn test_render_marker() {
+    let source = vec!["@10001".to_string(), " eve market 吉他 x".to_string()];
+    let err = BotError::Syntax {
+        found: Some("吉他".into()),
+        expected: None,
+        note: None,
+        span: Some(Span {
+            segment: 1,
+            start: 12,
+            end: 14,
+        }),
+    };
+    assert_eq!(
+        err.marker(&source).unwrap(),
+        "@10001 eve market 吉他 x\n                  ^^^^"
+    );
+    let group = BotErrorGroup::from(err);
+    assert!(group.render(&source).ends_with("^^^^"));
+    assert_eq!(group.render(&[]), group.to_string());
 }
 
 impl From<reqwest::Error> for BotError {
     fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Self::Timeout;
+        }
         Self::Backend {
             code: err.status(),
             source: err.to_string(),
@@ -153,6 +240,7 @@ impl std::fmt::Display for BotError {
                 found,
                 expected,
                 note,
+                ..
             } => write!(
                 f,
                 "找到：{}\n期望：{}\n注释：{}",
@@ -168,9 +256,26 @@ impl std::fmt::Display for BotError {
                         .unwrap_or("<UNREACHABLE>".into())
                 )
             }
+            Self::Timeout => write!(f, "后端繁忙，请稍后再试"),
+            Self::Ambiguous { candidates } => {
+                write!(f, "找到多个物品：")?;
+                for (i, item) in candidates.iter().enumerate() {
+                    write!(f, "\n{}. {}", i + 1, item.type_name)?;
+                }
+                Ok(())
+            }
             Self::FileSystem { content } => {
                 write!(f, "文件系统错误：{}", content)
             }
+            Self::Action { retcode, source } => {
+                write!(
+                    f,
+                    "OneBot 返回码：{}\n返回内容：{source}",
+                    retcode
+                        .map(|s| s.to_string())
+                        .unwrap_or("<UNREACHABLE>".into())
+                )
+            }
         }
     }
 }
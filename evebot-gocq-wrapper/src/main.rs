@@ -1,17 +1,36 @@
+mod backend;
 mod command;
+mod config;
 mod constant;
 mod error;
-mod metadata;
 mod server;
 mod utils;
 
 use actix_web::{web, App, HttpServer};
-use server::main_handler;
+use server::{
+    healthz_handler, main_handler, ws_api_handler, ws_event_handler, ws_universal_handler,
+    WsRegistry,
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| App::new().route("/", web::post().to(main_handler)))
-        .bind(("127.0.0.1", 8080))?
-        .run()
-        .await
+    let config = config::init(config::Config::load(
+        config::config_path_from_args(std::env::args().skip(1)).as_deref(),
+    )?);
+    // one connection pool and cache of the backend for every worker
+    let http = backend::HttpBackend::new(config).map_err(std::io::Error::other)?;
+    backend::init(backend::CachedBackend::new(http, &config.cache));
+    let registry = web::Data::new(WsRegistry::default());
+    HttpServer::new(move || {
+        App::new()
+            .app_data(registry.clone())
+            .route("/", web::post().to(main_handler))
+            .route("/healthz", web::get().to(healthz_handler))
+            .service(web::resource(["/ws", "/ws/"]).route(web::get().to(ws_universal_handler)))
+            .route("/ws/event", web::get().to(ws_event_handler))
+            .route("/ws/api", web::get().to(ws_api_handler))
+    })
+    .bind((config.server.host.as_str(), config.server.port))?
+    .run()
+    .await
 }
@@ -1,5 +0,0 @@
-#![allow(dead_code)]
-
-pub const BOT_UID: u64 = 0;
-pub const LOCAL_EVE_SERVICE_PORT: usize = 8000;
-pub const IMAGE_DIRECTORY: &str = "D:/WBH/rust/evebot/go-cqhttp/Qsign-Onekey/data/images/MANUAL/";
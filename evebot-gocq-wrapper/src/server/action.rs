@@ -0,0 +1,106 @@
+use crate::server::data::{GroupMemberRole, GroupRequestType};
+use serde::{Deserialize, Serialize};
+
+/// Envelope of every OneBot action response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionResponse<T> {
+    pub status: String,
+    pub retcode: i64,
+    #[serde(default = "Option::default")]
+    pub data: Option<T>,
+    #[serde(default)]
+    pub msg: Option<String>,
+    #[serde(default)]
+    pub wording: Option<String>,
+    #[serde(default)]
+    pub echo: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendGroupMsg {
+    pub group_id: u64,
+    pub message: serde_json::Value,
+    #[serde(default)]
+    pub auto_escape: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendPrivateMsg {
+    pub user_id: u64,
+    pub message: serde_json::Value,
+    #[serde(default)]
+    pub auto_escape: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendGroupForwardMsg {
+    pub group_id: u64,
+    pub messages: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DeleteMsg {
+    pub message_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetFriendAddRequest {
+    pub flag: String,
+    pub approve: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetGroupAddRequest {
+    pub flag: String,
+    pub sub_type: GroupRequestType,
+    pub approve: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GetGroupMemberInfo {
+    pub group_id: u64,
+    pub user_id: u64,
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GetLoginInfo {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageId {
+    pub message_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardId {
+    #[serde(default)]
+    pub message_id: Option<i32>,
+    #[serde(default)]
+    pub forward_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMemberInfo {
+    pub group_id: u64,
+    pub user_id: u64,
+    pub nickname: String,
+    #[serde(default)]
+    pub card: String,
+    #[serde(default)]
+    pub join_time: i64,
+    #[serde(default)]
+    pub last_sent_time: i64,
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub role: Option<GroupMemberRole>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginInfo {
+    pub user_id: u64,
+    pub nickname: String,
+}
@@ -0,0 +1,281 @@
+use crate::config::config;
+use crate::error::{BotError, BotResult};
+use crate::server::action::*;
+use crate::server::data::{GroupRequestType, MessageEvent};
+use crate::server::ws::{reply_action, WsRegistry};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Client for OneBot v11 actions.
+///
+/// Unlike the quick-operation reply of the HTTP handler, this can talk to go-cqhttp
+/// at any time, so it is also what scheduled or long-running jobs should use.
+#[derive(Clone)]
+pub enum OneBotApi {
+    /// go-cqhttp's HTTP API, e.g. `http://127.0.0.1:5700`.
+    Http {
+        base_url: String,
+        access_token: Option<String>,
+        client: reqwest::Client,
+    },
+    /// An action-capable reverse-WebSocket connection of the account `self_id`.
+    Ws { registry: WsRegistry, self_id: u64 },
+}
+
+#[allow(dead_code)]
+impl OneBotApi {
+    pub fn http(base_url: impl Into<String>) -> Self {
+        Self::Http {
+            base_url: base_url.into(),
+            access_token: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    #[inline]
+    pub fn ws(registry: WsRegistry, self_id: u64) -> Self {
+        Self::Ws { registry, self_id }
+    }
+
+    /// Only meaningful for the HTTP backend; the WebSocket one authenticates on connect.
+    pub fn with_access_token(mut self, token: impl Into<String>) -> Self {
+        if let Self::Http { access_token, .. } = &mut self {
+            *access_token = Some(token.into());
+        }
+        self
+    }
+
+    pub async fn send_group_msg(
+        &self,
+        group_id: u64,
+        message: serde_json::Value,
+    ) -> BotResult<MessageId> {
+        self.call(
+            "send_group_msg",
+            &SendGroupMsg {
+                group_id,
+                message,
+                auto_escape: false,
+            },
+        )
+        .await
+    }
+
+    pub async fn send_private_msg(
+        &self,
+        user_id: u64,
+        message: serde_json::Value,
+    ) -> BotResult<MessageId> {
+        self.call(
+            "send_private_msg",
+            &SendPrivateMsg {
+                user_id,
+                message,
+                auto_escape: false,
+            },
+        )
+        .await
+    }
+
+    pub async fn send_group_forward_msg(
+        &self,
+        group_id: u64,
+        messages: serde_json::Value,
+    ) -> BotResult<ForwardId> {
+        self.call(
+            "send_group_forward_msg",
+            &SendGroupForwardMsg { group_id, messages },
+        )
+        .await
+    }
+
+    pub async fn delete_msg(&self, message_id: i32) -> BotResult<()> {
+        self.call("delete_msg", &DeleteMsg { message_id }).await
+    }
+
+    pub async fn get_group_member_info(
+        &self,
+        group_id: u64,
+        user_id: u64,
+        no_cache: bool,
+    ) -> BotResult<GroupMemberInfo> {
+        self.call(
+            "get_group_member_info",
+            &GetGroupMemberInfo {
+                group_id,
+                user_id,
+                no_cache,
+            },
+        )
+        .await
+    }
+
+    pub async fn set_friend_add_request(&self, flag: &str, approve: bool) -> BotResult<()> {
+        self.call(
+            "set_friend_add_request",
+            &SetFriendAddRequest {
+                flag: flag.to_owned(),
+                approve,
+            },
+        )
+        .await
+    }
+
+    pub async fn set_group_add_request(
+        &self,
+        flag: &str,
+        sub_type: GroupRequestType,
+        approve: bool,
+    ) -> BotResult<()> {
+        self.call(
+            "set_group_add_request",
+            &SetGroupAddRequest {
+                flag: flag.to_owned(),
+                sub_type,
+                approve,
+            },
+        )
+        .await
+    }
+
+    pub async fn get_login_info(&self) -> BotResult<LoginInfo> {
+        self.call("get_login_info", &GetLoginInfo {}).await
+    }
+
+    /// Send `message` to the group or private chat that `data` came from.
+    pub async fn reply(
+        &self,
+        data: &MessageEvent,
+        message: serde_json::Value,
+    ) -> BotResult<MessageId> {
+        let frame = reply_action(data, message);
+        let action = frame["action"].as_str().unwrap_or_default();
+        self.call(action, &frame["params"]).await
+    }
+
+    pub async fn call<P: Serialize, R: DeserializeOwned>(
+        &self,
+        action: &str,
+        params: &P,
+    ) -> BotResult<R> {
+        let resp: ActionResponse<serde_json::Value> = match self {
+            Self::Http {
+                base_url,
+                access_token,
+                client,
+            } => {
+                let mut req = client
+                    .post(format!("{}/{}", base_url.trim_end_matches('/'), action))
+                    .json(params);
+                if let Some(token) = access_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send().await?.error_for_status()?.json().await?
+            }
+            Self::Ws { registry, self_id } => {
+                let params = serde_json::to_value(params).map_err(|e| BotError::Action {
+                    retcode: None,
+                    source: e.to_string(),
+                })?;
+                registry
+                    .call(*self_id, action, params, config().timeout.action())
+                    .await?
+            }
+        };
+        if resp.retcode != 0 {
+            return Err(BotError::Action {
+                retcode: Some(resp.retcode),
+                source: format!(
+                    "{}: {}",
+                    action,
+                    resp.wording.or(resp.msg).unwrap_or(resp.status)
+                ),
+            });
+        }
+        serde_json::from_value(resp.data.unwrap_or_default()).map_err(|e| BotError::Action {
+            retcode: Some(resp.retcode),
+            source: format!("{}: {}", action, e),
+        })
+    }
+}
+
+#[test]
+fn test_http_api() {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    async fn send_group_msg(body: web::Json<SendGroupMsg>) -> HttpResponse {
+        HttpResponse::Ok().json(json! {{
+            "status": "ok",
+            "retcode": 0,
+            "data": { "message_id": body.group_id as i32 + 1 }
+        }})
+    }
+
+    async fn delete_msg() -> HttpResponse {
+        HttpResponse::Ok().json(json! {{
+            "status": "failed",
+            "retcode": 100,
+            "data": null,
+            "msg": "MESSAGE_NOT_FOUND",
+            "wording": "消息不存在"
+        }})
+    }
+
+    async fn get_login_info(req: HttpRequest) -> HttpResponse {
+        if req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            != Some("Bearer secret")
+        {
+            return HttpResponse::Unauthorized().finish();
+        }
+        HttpResponse::Ok().json(json! {{
+            "status": "ok",
+            "retcode": 0,
+            "data": { "user_id": 5050, "nickname": "evebot" }
+        }})
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/send_group_msg", web::post().to(send_group_msg))
+                .route("/delete_msg", web::post().to(delete_msg))
+                .route("/get_login_info", web::post().to(get_login_info))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let api = OneBotApi::http(format!("http://{}/", addr));
+        assert_eq!(
+            api.send_group_msg(41, json!("hello")).await.unwrap(),
+            MessageId { message_id: 42 }
+        );
+        assert!(matches!(
+            api.delete_msg(42).await,
+            Err(BotError::Action {
+                retcode: Some(100),
+                ..
+            })
+        ));
+        assert!(api.get_login_info().await.is_err());
+        assert_eq!(
+            api.with_access_token("secret")
+                .get_login_info()
+                .await
+                .unwrap(),
+            LoginInfo {
+                user_id: 5050,
+                nickname: "evebot".into()
+            }
+        );
+        handle.stop(false).await;
+    });
+}
@@ -0,0 +1,138 @@
+use crate::config::ServerConfig;
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::HttpResponse;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// Why a request from OneBot was turned down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// No credentials were sent, answered with `401`.
+    Missing,
+    /// The credentials were wrong, answered with `403`.
+    Invalid,
+}
+
+impl AuthError {
+    pub fn to_response(self) -> HttpResponse {
+        match self {
+            Self::Missing => HttpResponse::Unauthorized().finish(),
+            Self::Invalid => HttpResponse::Forbidden().finish(),
+        }
+    }
+}
+
+/// Compare in time independent of where the first difference is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Check `Authorization: Bearer <token>` against `access_token`. Anything passes if it is empty.
+///
+/// The `Token` scheme of older go-cqhttp versions is accepted too.
+pub fn verify_token(headers: &HeaderMap, access_token: &str) -> Result<(), AuthError> {
+    if access_token.is_empty() {
+        return Ok(());
+    }
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            v.strip_prefix("Bearer ")
+                .or_else(|| v.strip_prefix("Token "))
+        })
+        .ok_or(AuthError::Missing)?;
+    if constant_time_eq(token.trim().as_bytes(), access_token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AuthError::Invalid)
+    }
+}
+
+/// Check `X-Signature: sha1=<hex>`, the HMAC-SHA1 of `body` keyed with `secret`.
+/// Anything passes if `secret` is empty.
+pub fn verify_signature(headers: &HeaderMap, body: &[u8], secret: &str) -> Result<(), AuthError> {
+    if secret.is_empty() {
+        return Ok(());
+    }
+    let signature = headers
+        .get("X-Signature")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("sha1="))
+        .ok_or(AuthError::Missing)?;
+    let signature = hex::decode(signature).map_err(|_| AuthError::Invalid)?;
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(secret.as_bytes()).map_err(|_| AuthError::Invalid)?;
+    mac.update(body);
+    mac.verify_slice(&signature).map_err(|_| AuthError::Invalid)
+}
+
+/// Verify an event posted over HTTP, before its body is parsed.
+#[inline]
+pub fn verify_request(
+    headers: &HeaderMap,
+    body: &[u8],
+    server: &ServerConfig,
+) -> Result<(), AuthError> {
+    verify_token(headers, &server.access_token)?;
+    verify_signature(headers, body, &server.secret)
+}
+
+#[test]
+fn test_verify_request() {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    let server = ServerConfig {
+        access_token: "token".into(),
+        secret: "secret".into(),
+        ..Default::default()
+    };
+    let body = br#"{"post_type":"meta_event"}"#;
+    let headers = |pairs: &[(&'static str, &str)]| {
+        let mut map = HeaderMap::new();
+        for (k, v) in pairs {
+            map.insert(
+                HeaderName::from_static(k),
+                HeaderValue::from_str(v).unwrap(),
+            );
+        }
+        map
+    };
+    let signature = {
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        format!("sha1={}", hex::encode(mac.finalize().into_bytes()))
+    };
+
+    let valid = headers(&[
+        ("authorization", "Bearer token"),
+        ("x-signature", &signature),
+    ]);
+    assert_eq!(verify_request(&valid, body, &server), Ok(()));
+    assert_eq!(
+        verify_request(&valid, b"{}", &server),
+        Err(AuthError::Invalid)
+    );
+    assert_eq!(
+        verify_request(&headers(&[("x-signature", &signature)]), body, &server),
+        Err(AuthError::Missing)
+    );
+    assert_eq!(
+        verify_request(
+            &headers(&[
+                ("authorization", "Bearer nope"),
+                ("x-signature", &signature)
+            ]),
+            body,
+            &server
+        ),
+        Err(AuthError::Invalid)
+    );
+    assert_eq!(
+        verify_request(&headers(&[("authorization", "Token token")]), body, &server),
+        Err(AuthError::Missing)
+    );
+    assert_eq!(
+        verify_request(&HeaderMap::new(), body, &ServerConfig::default()),
+        Ok(())
+    );
+}
@@ -0,0 +1,125 @@
+//! The CQ-code string form of messages, used when go-cqhttp runs with `message_format: string`
+//! and by OneBot implementations that do not speak the array form.
+//!
+//! A CQ-code message is plain text with segments such as `[CQ:at,qq=123]` embedded in it.
+
+use crate::server::Segment;
+use serde_json::{json, Value as jsv};
+
+/// Unescape plain text of a CQ-code message.
+fn unescape_text(text: &str) -> String {
+    text.replace("&#91;", "[")
+        .replace("&#93;", "]")
+        .replace("&amp;", "&")
+}
+
+/// Unescape a parameter value of a CQ code.
+fn unescape_param(text: &str) -> String {
+    unescape_text(&text.replace("&#44;", ","))
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('[', "&#91;")
+        .replace(']', "&#93;")
+}
+
+fn escape_param(text: &str) -> String {
+    escape_text(text).replace(',', "&#44;")
+}
+
+fn text_segment(text: &str) -> Segment {
+    Segment::Text {
+        text: unescape_text(text),
+    }
+}
+
+/// Parse a CQ-code message into segments.
+///
+/// An unterminated `[CQ:` is kept as text.
+pub fn parse_cq_code(message: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut rest = message;
+    while let Some(start) = rest.find("[CQ:") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        if start > 0 {
+            segments.push(text_segment(&rest[..start]));
+        }
+        let mut parts = rest[start + 4..start + len].split(',');
+        let seg_type = parts.next().unwrap_or_default();
+        let data = parts
+            .filter_map(|part| part.split_once('='))
+            .map(|(key, value)| (key.to_owned(), jsv::String(unescape_param(value))))
+            .collect::<serde_json::Map<_, _>>();
+        segments.push(Segment::from_value(json! {{
+            "type": seg_type,
+            "data": data
+        }}));
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(text_segment(rest));
+    }
+    segments
+}
+
+/// Serialize segments into the CQ-code form. Unknown segments are left out.
+pub fn to_cq_code(segments: &[Segment]) -> String {
+    segments.iter().map(segment_to_cq_code).collect()
+}
+
+fn segment_to_cq_code(segment: &Segment) -> String {
+    match segment {
+        Segment::Text { text } => return escape_text(text),
+        Segment::Unknown => return String::new(),
+        _ => {}
+    }
+    let value = serde_json::to_value(segment).unwrap_or_default();
+    let seg_type = value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default();
+    let params = value
+        .get("data")
+        .and_then(|d| d.as_object())
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| match value {
+            jsv::Null => None,
+            jsv::String(s) => Some(format!(",{}={}", key, escape_param(s))),
+            other => Some(format!(",{}={}", key, escape_param(&other.to_string()))),
+        })
+        .collect::<String>();
+    format!("[CQ:{}{}]", seg_type, params)
+}
+
+#[test]
+fn test_cq_code() {
+    let segments = parse_cq_code(
+        "[CQ:at,qq=123] eve a&#91;1&#93; [CQ:reply,id=-5&#44;6&amp;]x[CQ:poke,qq=1][CQ:",
+    );
+    assert_eq!(
+        segments,
+        vec![
+            Segment::At { qq: "123".into() },
+            text_segment(" eve a&#91;1&#93; "),
+            Segment::Reply { id: "-5,6&".into() },
+            text_segment("x"),
+            Segment::Unknown,
+            text_segment("[CQ:"),
+        ]
+    );
+    assert_eq!(
+        to_cq_code(&segments),
+        "[CQ:at,qq=123] eve a&#91;1&#93; [CQ:reply,id=-5&#44;6&amp;]x&#91;CQ:"
+    );
+    assert_eq!(
+        to_cq_code(&[Segment::Image {
+            file: "a,b.png".into(),
+            url: None
+        }]),
+        "[CQ:image,file=a&#44;b.png]"
+    );
+}
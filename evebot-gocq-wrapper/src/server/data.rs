@@ -1,21 +1,32 @@
 use crate::server::MessageData;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum PostType {
+/// An event reported by OneBot, by `post_type`.
+///
+/// The bot's own messages (`message_sent`) are not handled and fail to deserialize.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "post_type")]
+pub enum Event {
     #[serde(rename = "message")]
-    Message,
-    #[serde(rename = "message_sent")]
-    MessageSent,
-    #[serde(rename = "request")]
-    Request,
+    Message(MessageEvent),
     #[serde(rename = "notice")]
-    Notice,
+    Notice(NoticeEvent),
+    #[serde(rename = "request")]
+    Request(RequestEvent),
     #[serde(rename = "meta_event")]
-    MetaEvent,
+    Meta(MetaEvent),
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageKind {
+    #[serde(rename = "group")]
+    Group,
+    #[serde(rename = "private")]
+    Private,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     #[serde(rename = "normal")]
     Normal,
@@ -23,8 +34,17 @@ pub enum MessageType {
     Anonymous,
     #[serde(rename = "notice")]
     Notice,
+    /// A private message from a friend.
+    #[serde(rename = "friend")]
+    Friend,
+    /// A private message started from a group.
+    #[serde(rename = "group")]
+    Group,
+    #[serde(other, rename = "other")]
+    Other,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupMember {
     pub user_id: u64,
@@ -43,15 +63,137 @@ pub enum GroupMemberRole {
     Member,
 }
 
+/// A group or private message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GroupMessage {
+pub struct MessageEvent {
     pub time: u64,
     pub self_id: u64,
-    pub post_type: PostType,
+    pub message_type: MessageKind,
     pub sub_type: MessageType,
-    pub message_id: u32,
+    pub message_id: i32,
     pub user_id: u64,
     pub message: MessageData,
     pub raw_message: String,
     pub group_id: Option<u64>,
 }
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "notice_type")]
+pub enum NoticeEvent {
+    /// Someone joined a group the bot is in.
+    #[serde(rename = "group_increase")]
+    GroupIncrease {
+        self_id: u64,
+        group_id: u64,
+        user_id: u64,
+        #[serde(default)]
+        operator_id: u64,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "request_type")]
+pub enum RequestEvent {
+    #[serde(rename = "friend")]
+    Friend {
+        self_id: u64,
+        user_id: u64,
+        #[serde(default)]
+        comment: String,
+        flag: String,
+    },
+    /// A request to join a group (`add`), or an invitation of the bot (`invite`).
+    #[serde(rename = "group")]
+    Group {
+        self_id: u64,
+        group_id: u64,
+        user_id: u64,
+        sub_type: GroupRequestType,
+        #[serde(default)]
+        comment: String,
+        flag: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupRequestType {
+    #[serde(rename = "add")]
+    Add,
+    #[serde(rename = "invite")]
+    Invite,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "meta_event_type")]
+pub enum MetaEvent {
+    #[serde(rename = "lifecycle")]
+    Lifecycle {
+        self_id: u64,
+        #[serde(default)]
+        sub_type: String,
+    },
+    #[serde(rename = "heartbeat")]
+    Heartbeat {
+        self_id: u64,
+        /// In milliseconds.
+        #[serde(default)]
+        interval: u64,
+        #[serde(default)]
+        status: serde_json::Value,
+    },
+}
+
+#[test]
+fn test_event() {
+    use serde_json::json;
+    let event = |v| serde_json::from_value::<Event>(v);
+    assert!(matches!(
+        event(json! {{
+            "post_type": "message", "message_type": "private", "sub_type": "friend",
+            "time": 1, "self_id": 2, "message_id": -3, "user_id": 4,
+            "message": "eve", "raw_message": "eve"
+        }}),
+        Ok(Event::Message(MessageEvent {
+            message_type: MessageKind::Private,
+            group_id: None,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        event(json! {{
+            "post_type": "notice", "notice_type": "group_increase", "sub_type": "approve",
+            "time": 1, "self_id": 2, "group_id": 3, "user_id": 4, "operator_id": 0
+        }}),
+        Ok(Event::Notice(NoticeEvent::GroupIncrease {
+            group_id: 3,
+            user_id: 4,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        event(json! {{ "post_type": "notice", "notice_type": "group_recall", "time": 1 }}),
+        Ok(Event::Notice(NoticeEvent::Other))
+    ));
+    assert!(matches!(
+        event(json! {{
+            "post_type": "request", "request_type": "group", "sub_type": "invite",
+            "time": 1, "self_id": 2, "group_id": 3, "user_id": 4, "comment": "", "flag": "f"
+        }}),
+        Ok(Event::Request(RequestEvent::Group {
+            sub_type: GroupRequestType::Invite,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        event(json! {{
+            "post_type": "meta_event", "meta_event_type": "heartbeat",
+            "time": 1, "self_id": 2, "interval": 5000, "status": {}
+        }}),
+        Ok(Event::Meta(MetaEvent::Heartbeat { interval: 5000, .. }))
+    ));
+    assert!(event(json! {{ "post_type": "message_sent", "time": 1 }}).is_err());
+}
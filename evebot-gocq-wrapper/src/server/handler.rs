@@ -1,34 +1,278 @@
-use crate::command;
-use crate::server::data::GroupMessage;
+use crate::backend::backend;
+use crate::command::distributor::dis::{self, PrefixMode};
+use crate::command::{
+    load_context, save_context, save_pending, with_account, with_group, CommandOutput, Context,
+    Sender,
+};
+use crate::config::{config, Account, PolicyConfig};
+use crate::error::BotResult;
+use crate::server::data::{
+    Event, GroupRequestType, MessageEvent, MessageKind, NoticeEvent, RequestEvent,
+};
+use crate::server::{track_meta, verify_request, MessageChain, OneBotApi, Segment};
 use actix_web::web;
-use actix_web::{HttpResponse, Result};
+use actix_web::{HttpRequest, HttpResponse, Result};
 use serde_json::json;
 
-pub async fn main_handler(payload: web::Payload) -> Result<HttpResponse> {
+pub async fn main_handler(req: HttpRequest, payload: web::Payload) -> Result<HttpResponse> {
     let data = payload.to_bytes().await?;
+    if let Err(err) = verify_request(req.headers(), &data, &config().server) {
+        return Ok(err.to_response());
+    }
     let json_data: serde_json::Value = serde_json::from_slice(&data)?;
-    if json_data.get("post_type") == Some(&json!("message")) {
-        group_message_handler(serde_json::from_slice(&data)?).await
-    } else {
-        Ok(HttpResponse::NoContent().finish())
+    // e.g. the bot's own messages
+    let Ok(event) = serde_json::from_value::<Event>(json_data) else {
+        return Ok(HttpResponse::NoContent().finish());
+    };
+    match event {
+        Event::Message(data) => message_handler(data).await,
+        Event::Request(request) => match approve_request(&request, &config().policy) {
+            Some(approve) => Ok(HttpResponse::Ok().json(json! {{ "approve": approve }})),
+            None => Ok(HttpResponse::NoContent().finish()),
+        },
+        Event::Notice(notice) => {
+            // a notice has no quick operation, so it needs the HTTP API of its account
+            if let NoticeEvent::GroupIncrease { self_id, .. } = notice {
+                if let Some(api) = http_api(&config().account(self_id)) {
+                    let _ = notice_action(&api, &notice).await;
+                }
+            }
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Event::Meta(meta) => {
+            track_meta(&meta);
+            Ok(HttpResponse::NoContent().finish())
+        }
     }
 }
 
-async fn group_message_handler(data: GroupMessage) -> Result<HttpResponse> {
-    let msg = data.message.into_messages();
-    if let Some(msg) = msg {
-        match command::distributor::dis::distribute(msg).await {
-            None => Ok(HttpResponse::NoContent().finish()),
-            Some(Err(err)) => Ok(HttpResponse::Ok().json(json! {{
-                "at_sender": false,
-                "reply": format!("{}", err)
-            }})),
-            Some(Ok(resp)) => Ok(HttpResponse::Ok().json(json! {{
-                "at_sender": false,
-                "reply": resp,
-            }})),
-        }
-    } else {
-        Ok(HttpResponse::NoContent().finish())
+/// go-cqhttp's HTTP API of `account`, if configured.
+fn http_api(account: &Account) -> Option<OneBotApi> {
+    if account.api_url.is_empty() {
+        return None;
+    }
+    let api = OneBotApi::http(&account.api_url);
+    Some(match config().server.access_token.as_str() {
+        "" => api,
+        token => api.with_access_token(token),
+    })
+}
+
+/// A quick operation does not tell the id of the answer, which a quote-reply to it refers to. So
+/// an answer that can be followed up on is sent through the HTTP API instead, if there is one.
+async fn message_handler(data: MessageEvent) -> Result<HttpResponse> {
+    let Some(reply) = message_reply(data.clone()).await else {
+        return Ok(HttpResponse::NoContent().finish());
+    };
+    if let (Some(context), Some(api)) = (&reply.context, http_api(&config().account(data.self_id)))
+    {
+        if let Ok(sent) = api.reply(&data, reply.message.clone()).await {
+            save_context(data.self_id, sent.message_id as i64, context.clone());
+            return Ok(HttpResponse::NoContent().finish());
+        }
+    }
+    Ok(HttpResponse::Ok().json(json! {{
+        "at_sender": false,
+        "reply": reply.message,
+    }}))
+}
+
+/// The answer to a message event.
+pub struct Reply {
+    pub message: serde_json::Value,
+    /// Kept by transports that learn the message id of the answer, see [`save_context`].
+    pub context: Option<Context>,
+}
+
+/// Run a message event through the distributor, with the settings of the account that
+/// received it and of the group it came from.
+///
+/// Returns the message to reply with, or `None` if the bot should stay silent.
+/// This is shared by every transport, so HTTP quick operations and WebSocket actions
+/// always answer with the same content.
+///
+/// A number answering a list of search candidates runs the command that asked for it. A
+/// quote-reply to an answer that has a context, or to the command itself, is tried as a
+/// follow-up next. In a private chat the prefix is optional, and in a group it may be
+/// optional for some commands, see [`crate::config::GroupConfig`].
+///
+/// A message in the CQ-code string form is answered in that form too.
+pub async fn message_reply(data: MessageEvent) -> Option<Reply> {
+    let account = config().account(data.self_id);
+    let group = data.group_id.and_then(|g| config().group(g)).cloned();
+    with_account(
+        account.clone(),
+        with_group(group, message_reply_as(data, account)),
+    )
+    .await
+}
+
+async fn message_reply_as(data: MessageEvent, account: Account) -> Option<Reply> {
+    let source = data.message.source();
+    let cq_code = data.message.is_cq_code();
+    let quoted = data
+        .message
+        .segments()?
+        .into_iter()
+        .find_map(|seg| match seg {
+            Segment::Reply { id } => load_context(data.self_id, id.parse().ok()?),
+            _ => None,
+        });
+    let sender = Sender {
+        user_id: data.user_id,
+        group_id: data.group_id,
+    };
+    let tokens = data.message.into_messages()?.collect::<Vec<_>>();
+    let output = dis::choose(data.self_id, sender, tokens.clone().into_iter(), backend()).await;
+    let output = match (output, quoted) {
+        (Some(output), _) => Some(output),
+        (None, Some(context)) => {
+            dis::follow_up(
+                context,
+                tokens.clone().into_iter(),
+                &account,
+                sender,
+                backend(),
+            )
+            .await
+        }
+        (None, None) => None,
+    };
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let mode = match (
+                data.message_type,
+                data.group_id.and_then(|g| config().group(g)),
+            ) {
+                (MessageKind::Private, _) => PrefixMode::Optional,
+                (_, Some(group)) if !group.prefixless.is_empty() => {
+                    PrefixMode::Only(&group.prefixless)
+                }
+                _ => PrefixMode::Required,
+            };
+            dis::distribute(tokens.into_iter(), &account, mode, sender, backend()).await?
+        }
+    };
+    let (message, context) = match output {
+        Err(err) => (MessageChain::new().text(err.render(&source)), None),
+        Ok(CommandOutput {
+            message,
+            context,
+            pending,
+        }) => {
+            if let Some(pending) = pending {
+                save_pending(data.self_id, sender, pending);
+            }
+            (message, context)
+        }
+    };
+    if let Some(context) = &context {
+        save_context(data.self_id, data.message_id as i64, context.clone());
     }
+    Some(Reply {
+        message: if cq_code {
+            json!(message.to_string())
+        } else {
+            message.to_value()
+        },
+        context,
+    })
+}
+
+/// Whether to approve `request` by the configured policy, or `None` to leave it pending.
+pub fn approve_request(request: &RequestEvent, policy: &PolicyConfig) -> Option<bool> {
+    match request {
+        RequestEvent::Friend { .. } if policy.accept_friend => Some(true),
+        RequestEvent::Group {
+            sub_type: GroupRequestType::Invite,
+            ..
+        } if policy.accept_group_invite => Some(true),
+        _ => None,
+    }
+}
+
+/// Answer `request` through `api`, for transports without quick operations.
+pub async fn request_action(api: &OneBotApi, request: &RequestEvent) -> BotResult<()> {
+    let Some(approve) = approve_request(request, &config().policy) else {
+        return Ok(());
+    };
+    match request {
+        RequestEvent::Friend { flag, .. } => api.set_friend_add_request(flag, approve).await,
+        RequestEvent::Group { flag, sub_type, .. } => {
+            api.set_group_add_request(flag, *sub_type, approve).await
+        }
+    }
+}
+
+/// The greeting for a new group member, and the group to send it to.
+pub fn greeting(notice: &NoticeEvent, policy: &PolicyConfig) -> Option<(u64, MessageChain)> {
+    let NoticeEvent::GroupIncrease {
+        self_id,
+        group_id,
+        user_id,
+        ..
+    } = notice
+    else {
+        return None;
+    };
+    let template = &policy.greeting;
+    // the bot joining a group is reported too
+    if template.is_empty() || user_id == self_id {
+        return None;
+    }
+    let mut message = MessageChain::new();
+    for (i, part) in template.split("{user}").enumerate() {
+        if i > 0 {
+            message = message.at(*user_id);
+        }
+        if !part.is_empty() {
+            message = message.text(part);
+        }
+    }
+    Some((*group_id, message))
+}
+
+pub async fn notice_action(api: &OneBotApi, notice: &NoticeEvent) -> BotResult<()> {
+    if let Some((group_id, message)) = greeting(notice, &config().policy) {
+        api.send_group_msg(group_id, message.to_value()).await?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_policy() {
+    let policy = PolicyConfig {
+        accept_friend: true,
+        accept_group_invite: false,
+        greeting: "欢迎 {user}！".into(),
+    };
+    let event = |v| serde_json::from_value::<Event>(v).unwrap();
+    let Event::Request(friend) = event(json! {{
+        "post_type": "request", "request_type": "friend",
+        "self_id": 1, "user_id": 2, "comment": "", "flag": "f"
+    }}) else {
+        unreachable!()
+    };
+    assert_eq!(approve_request(&friend, &policy), Some(true));
+    let Event::Request(invite) = event(json! {{
+        "post_type": "request", "request_type": "group", "sub_type": "invite",
+        "self_id": 1, "group_id": 3, "user_id": 2, "comment": "", "flag": "g"
+    }}) else {
+        unreachable!()
+    };
+    assert_eq!(approve_request(&invite, &policy), None);
+
+    let joined = |user_id: u64| match event(json! {{
+        "post_type": "notice", "notice_type": "group_increase", "sub_type": "approve",
+        "self_id": 1, "group_id": 3, "user_id": user_id, "operator_id": 0
+    }}) {
+        Event::Notice(notice) => greeting(&notice, &policy),
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        joined(2),
+        Some((3, MessageChain::new().text("欢迎 ").at(2).text("！")))
+    );
+    assert_eq!(joined(1), None);
 }
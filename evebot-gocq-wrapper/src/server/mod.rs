@@ -1,7 +1,21 @@
+mod action;
+mod api;
+mod auth;
+mod cq;
 mod data;
 mod handler;
 mod parser;
+mod segment;
+mod status;
+mod ws;
 
+pub use action::*;
+pub use api::*;
+pub use auth::*;
+pub use cq::*;
 pub use data::*;
 pub use handler::*;
 pub use parser::*;
+pub use segment::*;
+pub use status::*;
+pub use ws::*;
@@ -1,3 +1,5 @@
+use crate::error::{BotError, BotResult};
+use crate::server::{parse_cq_code, Segment};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as jsv;
 use std::ops::{Deref, DerefMut};
@@ -19,22 +21,62 @@ impl DerefMut for MessageData {
 }
 
 impl MessageData {
+    /// The segments of the message, parsing it first if it is in the CQ-code string form.
+    ///
+    /// Segments that fail to parse are kept as [`Segment::Unknown`], so indices stay the same.
+    pub fn segments(&self) -> Option<Vec<Segment>> {
+        match &self.0 {
+            jsv::Array(arr) => Some(arr.iter().cloned().map(Segment::from_value).collect()),
+            jsv::String(text) => Some(parse_cq_code(text)),
+            _ => None,
+        }
+    }
+
+    /// Whether the message is in the CQ-code string form, so replies should be too.
+    pub fn is_cq_code(&self) -> bool {
+        self.0.is_string()
+    }
+
     pub fn into_messages(self) -> Option<MessageIter> {
-        if let jsv::Array(arr) = self.0 {
-            Some(MessageIter {
-                message: arr.into_iter(),
-                cache_string: None,
+        Some(MessageIter {
+            message: self.segments()?.into_iter().enumerate(),
+            cache_string: None,
+            failed: false,
+        })
+    }
+
+    /// The text of every segment as it is echoed back in error messages.
+    ///
+    /// Indices match [`Span::segment`].
+    pub fn source(&self) -> Vec<String> {
+        let Some(segments) = self.segments() else {
+            return vec![];
+        };
+        segments
+            .iter()
+            .map(|seg| match seg {
+                Segment::Text { text } => text.clone(),
+                Segment::At { qq } => format!("@{}", qq),
+                // shown above the message rather than in it
+                Segment::Reply { .. } | Segment::Unknown => String::new(),
+                Segment::Image { .. } => "[图片]".into(),
+                Segment::Face { .. } => "[表情]".into(),
+                Segment::Record { .. } => "[语音]".into(),
+                Segment::Forward { .. } => "[聊天记录]".into(),
+                Segment::Json { .. } => "[卡片]".into(),
+                Segment::File { .. } => "[文件]".into(),
             })
-        } else {
-            None
-        }
+            .collect()
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct MessageIter {
-    message: std::vec::IntoIter<serde_json::Value>,
-    cache_string: Option<(Vec<char>, usize)>,
+    message: std::iter::Enumerate<std::vec::IntoIter<Segment>>,
+    /// Segment index, its characters and the read position.
+    cache_string: Option<(usize, Vec<char>, usize)>,
+    /// Set after a tokenization error, which ends the iteration.
+    failed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
@@ -52,36 +94,73 @@ impl std::fmt::Display for ParamItem {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-enum BracketType {
-    Brace,
-    Parenthesis,
-    Bracket,
+/// Characters `[start, end)` of the message segment `segment`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub segment: usize,
+    pub start: usize,
+    pub end: usize,
 }
 
-impl BracketType {
-    pub fn match_open(ch: &char) -> Option<Self> {
-        match ch {
-            '{' | '｛' => Some(Self::Brace),
-            '[' | '【' => Some(Self::Bracket),
-            '(' | '（' => Some(Self::Parenthesis),
-            _ => None,
+impl Span {
+    /// The span from the start of `self` to the end of `other`, if both are in the same segment.
+    pub fn to(self, other: Span) -> Span {
+        if self.segment == other.segment {
+            Span {
+                end: other.end.max(self.end),
+                ..self
+            }
+        } else {
+            self
         }
     }
+}
 
-    pub fn match_close(ch: &char) -> Option<Self> {
-        match ch {
-            '}' | '｝' => Some(Self::Brace),
-            ']' | '】' => Some(Self::Bracket),
-            ')' | '）' => Some(Self::Parenthesis),
-            _ => None,
-        }
+/// A [`ParamItem`] and where it came from.
+///
+/// Items built in code have no span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub item: ParamItem,
+    pub span: Option<Span>,
+}
+
+impl From<ParamItem> for Token {
+    #[inline]
+    fn from(item: ParamItem) -> Self {
+        Self { item, span: None }
     }
 }
 
-#[inline(always)]
-fn is_whitespace(ch: &char) -> bool {
-    matches!(ch, ' ' | '\t' | '\n' | '\r')
+impl std::fmt::Display for Token {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.item.fmt(f)
+    }
+}
+
+/// The characters closing a group opened by `ch`, if `ch` opens one.
+///
+/// Full-width and half-width brackets close each other.
+fn group_close(ch: char) -> Option<&'static [char]> {
+    match ch {
+        '{' | '｛' => Some(&['}', '｝']),
+        '[' | '【' => Some(&[']', '】']),
+        '(' | '（' => Some(&[')', '）']),
+        '"' => Some(&['"']),
+        '“' => Some(&['”']),
+        '「' => Some(&['」']),
+        _ => None,
+    }
+}
+
+/// Whether a backslash before `ch` escapes it. Only quotes and the characters closing a group
+/// may be escaped, every other backslash is kept as it is.
+fn escapable(ch: char) -> bool {
+    matches!(
+        ch,
+        '"' | '“' | '”' | '「' | '」' | '}' | '｝' | ']' | '】' | ')' | '）'
+    )
 }
 
 #[test]
@@ -98,7 +177,7 @@ fn test_msg_iter() {
         {
             "type": "text",
             "data": {
-                "text": "  早上好啊 13 (1a  nd}[[c）123"
+                "text": "  早上好啊 13 (1a  nd}[[c）123 “Raven Navy Issue” \"a \\\"b\\\"\" 「x」 y"
             }
         },
         {
@@ -108,86 +187,187 @@ fn test_msg_iter() {
             }
         },
     ]};
-    for message in MessageData(json_val.clone()).into_messages().unwrap() {
-        println!("{:?}", message);
-    }
+    let tokens = MessageData(json_val.clone())
+        .into_messages()
+        .unwrap()
+        .collect::<BotResult<Vec<_>>>()
+        .unwrap();
     assert_eq!(
-        MessageData(json_val)
-            .into_messages()
-            .unwrap()
-            .collect::<Vec<_>>(),
+        tokens.iter().map(|t| t.item.clone()).collect::<Vec<_>>(),
         vec![
             At(123456),
             Text("早上好啊".into()),
             Text("13".into()),
             Text("1a  nd}[[c".into()),
             Text("123".into()),
+            Text("Raven Navy Issue".into()),
+            Text("a \"b\"".into()),
+            Text("x".into()),
+            Text("y".into()),
             At(123777)
         ]
-    )
-}
+    );
+    let span = |segment, start, end| {
+        Some(Span {
+            segment,
+            start,
+            end,
+        })
+    };
+    assert_eq!(tokens[0].span, span(0, 0, 7));
+    assert_eq!(tokens[1].span, span(1, 2, 6));
+    assert_eq!(tokens[3].span, span(1, 10, 22));
+    assert_eq!(tokens[8].span, span(1, 59, 60));
+    assert_eq!(MessageData(json_val).source()[2], "@123777");
+    let cq_code = MessageData(json!("[CQ:at,qq=123456] eve 「a b」"));
+    assert_eq!(cq_code.source(), vec!["@123456", " eve 「a b」"]);
+    assert_eq!(
+        cq_code
+            .into_messages()
+            .unwrap()
+            .map(|t| t.unwrap().item)
+            .collect::<Vec<_>>(),
+        vec![At(123456), Text("eve".into()), Text("a b".into())]
+    );
+    let quoted = MessageData(json! {[
+        { "type": "reply", "data": { "id": "-1" } },
+        { "type": "at", "data": { "qq": "123456" } },
+        { "type": "face", "data": { "id": "178" } },
+        { "type": "text", "data": { "text": " eve x" } },
+    ]});
+    assert_eq!(quoted.source().concat(), "@123456[表情] eve x");
+    assert_eq!(
+        quoted
+            .into_messages()
+            .unwrap()
+            .map(|t| t.unwrap().item)
+            .collect::<Vec<_>>(),
+        vec![At(123456), Text("eve".into()), Text("x".into())]
+    );
 
-impl Iterator for MessageIter {
-    type Item = ParamItem;
+    let unclosed = MessageData(json! {[{ "type": "text", "data": { "text": "eve (abc" } }]})
+        .into_messages()
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(unclosed.len(), 2);
+    assert!(matches!(
+        &unclosed[1],
+        Err(BotError::Syntax { span: s, .. }) if *s == span(0, 4, 8)
+    ));
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some((ref cache_string, ref mut ptr)) = self.cache_string {
-            while *ptr < cache_string.len() && is_whitespace(&cache_string[*ptr]) {
+    // only quotes and closing characters are escaped, other backslashes are kept
+    let escaped = MessageData(json!("a\\%b a\\\"b \"c\\\"d\""))
+        .into_messages()
+        .unwrap()
+        .map(|t| t.unwrap().item)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        escaped,
+        vec![
+            Text("a\\%b".into()),
+            Text("a\"b".into()),
+            Text("c\"d".into())
+        ]
+    );
+}
+
+impl MessageIter {
+    /// The next token of the cached text segment, if any is left.
+    fn next_in_text(&mut self) -> Option<BotResult<Token>> {
+        let (segment, chars, ptr) = self.cache_string.as_mut()?;
+        while *ptr < chars.len() && chars[*ptr].is_whitespace() {
+            *ptr += 1;
+        }
+        if *ptr >= chars.len() {
+            self.cache_string = None;
+            return None;
+        }
+        let start = *ptr;
+        let span = |end| {
+            Some(Span {
+                segment: *segment,
+                start,
+                end,
+            })
+        };
+        let mut text = String::new();
+        let mut push_escaped = |ptr: &mut usize| {
+            if chars[*ptr] == '\\' && chars.get(*ptr + 1).is_some_and(|&ch| escapable(ch)) {
                 *ptr += 1;
             }
-            if *ptr + 1 >= cache_string.len() {
-                self.cache_string = None;
-                return self.next();
-            }
-            if let Some(bracket) = BracketType::match_open(&cache_string[*ptr]) {
-                let mut text = String::new();
-                while *ptr < cache_string.len() {
-                    *ptr += 1;
-                    if BracketType::match_close(&cache_string[*ptr]).is_some_and(|v| v == bracket) {
-                        *ptr += 1;
-                        return Some(ParamItem::Text(text));
-                    }
-                    text.push(cache_string[*ptr]);
+            text.push(chars[*ptr]);
+            *ptr += 1;
+        };
+        if let Some(close) = group_close(chars[start]) {
+            *ptr += 1;
+            loop {
+                if *ptr >= chars.len() {
+                    let found = chars[start..].iter().collect();
+                    let span = span(chars.len());
+                    self.cache_string = None;
+                    self.failed = true;
+                    return Some(Err(BotError::Syntax {
+                        found: Some(found),
+                        expected: Some(close[0].to_string()),
+                        note: Some("括号或引号未闭合".into()),
+                        span,
+                    }));
                 }
-                self.cache_string = None;
-                Some(ParamItem::Text(text))
-            } else {
-                let mut text = String::new();
-                while *ptr < cache_string.len()
-                    && BracketType::match_open(&cache_string[*ptr]).is_none()
-                    && !is_whitespace(&cache_string[*ptr])
-                {
-                    text.push(cache_string[*ptr]);
+                if close.contains(&chars[*ptr]) {
                     *ptr += 1;
+                    break;
                 }
-                if *ptr + 1 == cache_string.len() {
-                    self.cache_string = None;
-                }
-                Some(ParamItem::Text(text))
+                push_escaped(ptr);
             }
         } else {
-            let nxt = self.message.next()?;
-            let json_type = nxt.get("type")?;
-            let json_data = nxt.get("data")?;
-            match json_type {
-                jsv::String(text) if text == "at" => json_data
-                    .get("qq")
-                    .and_then(|v| v.as_u64())
-                    .map(ParamItem::At),
-                jsv::String(text) if text == "text" => json_data
-                    .get("text")
-                    .and_then(|s| {
-                        if let jsv::String(string) = s {
-                            Some(string.to_owned())
-                        } else {
-                            None
-                        }
-                    })
-                    .and_then(|s| {
-                        self.cache_string = Some((s.chars().collect(), 0));
-                        self.next()
-                    }),
-                _ => None,
+            while *ptr < chars.len()
+                && !chars[*ptr].is_whitespace()
+                && group_close(chars[*ptr]).is_none()
+            {
+                push_escaped(ptr);
+            }
+        }
+        Some(Ok(Token {
+            item: ParamItem::Text(text),
+            span: span(*ptr),
+        }))
+    }
+}
+
+impl Iterator for MessageIter {
+    type Item = BotResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if let Some(token) = self.next_in_text() {
+            return Some(token);
+        }
+        // other segments, such as the quote of a reply or an emoji, carry no arguments
+        loop {
+            match self.message.next()? {
+                (segment, Segment::At { qq }) => {
+                    // `@all` is not an argument either
+                    let Ok(qq) = qq.parse() else {
+                        continue;
+                    };
+                    return Some(Ok(Token {
+                        item: ParamItem::At(qq),
+                        span: Some(Span {
+                            segment,
+                            start: 0,
+                            end: format!("@{}", qq).chars().count(),
+                        }),
+                    }));
+                }
+                (segment, Segment::Text { text }) => {
+                    self.cache_string = Some((segment, text.chars().collect(), 0));
+                    if let Some(token) = self.next_in_text() {
+                        return Some(token);
+                    }
+                }
+                _ => {}
             }
         }
     }
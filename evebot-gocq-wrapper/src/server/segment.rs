@@ -0,0 +1,204 @@
+use crate::server::to_cq_code;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Accept both `123` and `"123"`, as implementations disagree on the type of ids.
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a string or a number, found {}",
+            other
+        ))),
+    }
+}
+
+/// A message segment in the OneBot array form, `{"type": ..., "data": {...}}`.
+///
+/// Only the fields the bot uses are kept, so a deserialized segment may not serialize back to
+/// the exact input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum Segment {
+    Text {
+        text: String,
+    },
+    /// `qq` is a user id, or `all`.
+    At {
+        #[serde(deserialize_with = "string_or_number")]
+        qq: String,
+    },
+    /// A quote of the message `id`.
+    Reply {
+        #[serde(deserialize_with = "string_or_number")]
+        id: String,
+    },
+    Image {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    Face {
+        #[serde(deserialize_with = "string_or_number")]
+        id: String,
+    },
+    Record {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    Forward {
+        #[serde(deserialize_with = "string_or_number")]
+        id: String,
+    },
+    Json {
+        data: String,
+    },
+    File {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// Any segment type above that failed to parse, or a type not listed.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Segment {
+    /// Parse a segment, falling back to [`Segment::Unknown`].
+    pub fn from_value(value: serde_json::Value) -> Segment {
+        serde_json::from_value(value).unwrap_or(Segment::Unknown)
+    }
+}
+
+/// A reply built up from segments.
+///
+/// ```
+/// MessageChain::new().reply(message_id).text("Done: ").image(uri)
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageChain(Vec<Segment>);
+
+#[allow(dead_code)]
+impl MessageChain {
+    #[inline]
+    pub fn new() -> MessageChain {
+        Self(vec![])
+    }
+
+    #[inline]
+    pub fn push(&mut self, segment: Segment) {
+        self.0.push(segment);
+    }
+
+    #[inline]
+    pub fn with(mut self, segment: Segment) -> MessageChain {
+        self.push(segment);
+        self
+    }
+
+    #[inline]
+    pub fn text(self, text: impl Into<String>) -> MessageChain {
+        self.with(Segment::Text { text: text.into() })
+    }
+
+    #[inline]
+    pub fn image(self, file: impl Into<String>) -> MessageChain {
+        self.with(Segment::Image {
+            file: file.into(),
+            url: None,
+        })
+    }
+
+    #[inline]
+    pub fn at(self, qq: u64) -> MessageChain {
+        self.with(Segment::At { qq: qq.to_string() })
+    }
+
+    #[inline]
+    pub fn reply(self, id: impl ToString) -> MessageChain {
+        self.with(Segment::Reply { id: id.to_string() })
+    }
+
+    #[inline]
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Vec<Segment> {
+        self.0
+    }
+
+    #[inline]
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl From<Vec<Segment>> for MessageChain {
+    #[inline]
+    fn from(value: Vec<Segment>) -> Self {
+        Self(value)
+    }
+}
+
+/// The CQ-code form of the chain.
+impl std::fmt::Display for MessageChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_cq_code(&self.0))
+    }
+}
+
+#[test]
+fn test_segment() {
+    use serde_json::json;
+    let segments = json! {[
+        { "type": "reply", "data": { "id": -12345 } },
+        { "type": "at", "data": { "qq": "10001" } },
+        { "type": "face", "data": { "id": "178" } },
+        { "type": "image", "data": { "file": "a.png", "url": "http://a/a.png", "subType": 0 } },
+        { "type": "text", "data": { "text": "hi" } },
+        { "type": "poke", "data": { "qq": 1 } },
+        { "type": "image", "data": {} },
+    ]};
+    let segments = match segments {
+        serde_json::Value::Array(arr) => arr.into_iter().map(Segment::from_value),
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        segments.collect::<Vec<_>>(),
+        vec![
+            Segment::Reply {
+                id: "-12345".into()
+            },
+            Segment::At { qq: "10001".into() },
+            Segment::Face { id: "178".into() },
+            Segment::Image {
+                file: "a.png".into(),
+                url: Some("http://a/a.png".into())
+            },
+            Segment::Text { text: "hi".into() },
+            Segment::Unknown,
+            Segment::Unknown,
+        ]
+    );
+
+    let chain = MessageChain::new()
+        .reply(42)
+        .text("价格：")
+        .image("file:///a.png");
+    assert_eq!(
+        chain.to_value(),
+        json! {[
+            { "type": "reply", "data": { "id": "42" } },
+            { "type": "text", "data": { "text": "价格：" } },
+            { "type": "image", "data": { "file": "file:///a.png" } },
+        ]}
+    );
+    assert_eq!(
+        chain.to_string(),
+        "[CQ:reply,id=42]价格：[CQ:image,file=file:///a.png]"
+    );
+}
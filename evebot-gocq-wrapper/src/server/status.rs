@@ -0,0 +1,217 @@
+use crate::config::config;
+use crate::server::data::MetaEvent;
+use actix_web::HttpResponse;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many heartbeats in a row may go missing before an account is reported as down.
+const MISSED_HEARTBEATS: u32 = 2;
+
+/// The connection of one account, as told by its meta events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountStatus {
+    /// As reported by the last lifecycle or heartbeat event.
+    pub online: bool,
+    /// When the account connected, or when the bot first heard of it.
+    pub since: Instant,
+    pub last_heartbeat: Option<Instant>,
+    /// As reported by the last heartbeat, or the configured default.
+    pub interval: Duration,
+}
+
+impl AccountStatus {
+    fn new(now: Instant, interval: Duration) -> Self {
+        Self {
+            online: true,
+            since: now,
+            last_heartbeat: None,
+            interval,
+        }
+    }
+
+    /// Whether more than [`MISSED_HEARTBEATS`] heartbeats have failed to arrive by `now`,
+    /// counted from `since` before the first one.
+    pub fn overdue(&self, now: Instant) -> bool {
+        let last = self.last_heartbeat.unwrap_or(self.since);
+        now.duration_since(last) > self.interval * (MISSED_HEARTBEATS + 1)
+    }
+
+    #[inline]
+    pub fn healthy(&self, now: Instant) -> bool {
+        self.online && !self.overdue(now)
+    }
+}
+
+/// The status of every account that sent a meta event, keyed by `self_id`.
+#[derive(Debug)]
+pub struct StatusTracker {
+    accounts: BTreeMap<u64, AccountStatus>,
+    /// Assumed until an account reports its own, or if it reports none.
+    default_interval: Duration,
+}
+
+impl StatusTracker {
+    pub fn new(default_interval: Duration) -> Self {
+        Self {
+            accounts: BTreeMap::new(),
+            default_interval,
+        }
+    }
+
+    pub fn record(&mut self, meta: &MetaEvent, now: Instant) {
+        match meta {
+            MetaEvent::Lifecycle { self_id, sub_type } => {
+                let status = self
+                    .accounts
+                    .entry(*self_id)
+                    .or_insert_with(|| AccountStatus::new(now, self.default_interval));
+                // `enable` and `connect` start a new session, `disable` ends it
+                status.online = sub_type != "disable";
+                status.since = now;
+                status.last_heartbeat = None;
+            }
+            MetaEvent::Heartbeat {
+                self_id,
+                interval,
+                status: reported,
+            } => {
+                let status = self
+                    .accounts
+                    .entry(*self_id)
+                    .or_insert_with(|| AccountStatus::new(now, self.default_interval));
+                status.online = reported
+                    .get("online")
+                    .and_then(serde_json::Value::as_bool)
+                    .unwrap_or(true);
+                status.last_heartbeat = Some(now);
+                // a heartbeat without an interval has `0`
+                status.interval = match *interval {
+                    0 => self.default_interval,
+                    ms => Duration::from_millis(ms),
+                };
+            }
+        }
+    }
+
+    /// Mark `self_id` as offline, e.g. when its WebSocket connection is closed.
+    pub fn disconnect(&mut self, self_id: u64) {
+        if let Some(status) = self.accounts.get_mut(&self_id) {
+            status.online = false;
+        }
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (u64, AccountStatus)> + '_ {
+        self.accounts.iter().map(|(id, status)| (*id, *status))
+    }
+}
+
+pub fn status_tracker() -> &'static Mutex<StatusTracker> {
+    static TRACKER: OnceLock<Mutex<StatusTracker>> = OnceLock::new();
+    TRACKER.get_or_init(|| Mutex::new(StatusTracker::new(config().timeout.heartbeat())))
+}
+
+pub fn track_meta(meta: &MetaEvent) {
+    if let Ok(mut tracker) = status_tracker().lock() {
+        tracker.record(meta, Instant::now());
+    }
+}
+
+pub fn track_disconnect(self_id: u64) {
+    if let Ok(mut tracker) = status_tracker().lock() {
+        tracker.disconnect(self_id);
+    }
+}
+
+/// A snapshot of every tracked account, by `self_id`.
+pub fn account_statuses() -> Vec<(u64, AccountStatus)> {
+    match status_tracker().lock() {
+        Ok(tracker) => tracker.accounts().collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// `GET /healthz`: `200` if every account is online and its heartbeats are on time, `503` if
+/// not, or if no account has connected yet.
+pub async fn healthz_handler() -> HttpResponse {
+    let now = Instant::now();
+    let accounts = account_statuses();
+    let healthy = !accounts.is_empty() && accounts.iter().all(|(_, s)| s.healthy(now));
+    let body = json! {{
+        "healthy": healthy,
+        "accounts": accounts
+            .iter()
+            .map(|(self_id, s)| json! {{
+                "self_id": self_id,
+                "online": s.online,
+                "healthy": s.healthy(now),
+                "uptime": now.duration_since(s.since).as_secs(),
+                "last_heartbeat": s.last_heartbeat.map(|t| now.duration_since(t).as_secs()),
+            }})
+            .collect::<Vec<_>>(),
+    }};
+    if healthy {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+#[test]
+fn test_status_tracker() {
+    let heartbeat = |online| MetaEvent::Heartbeat {
+        self_id: 1,
+        interval: 5000,
+        status: json! {{ "online": online, "good": true }},
+    };
+    let start = Instant::now();
+    let mut tracker = StatusTracker::new(Duration::from_secs(5));
+    tracker.record(
+        &MetaEvent::Lifecycle {
+            self_id: 1,
+            sub_type: "connect".into(),
+        },
+        start,
+    );
+    tracker.record(&heartbeat(true), start + Duration::from_secs(5));
+    let (_, status) = tracker.accounts().next().unwrap();
+    assert!(status.healthy(start + Duration::from_secs(20)));
+    assert!(!status.healthy(start + Duration::from_secs(21)));
+
+    tracker.record(&heartbeat(false), start + Duration::from_secs(10));
+    tracker.disconnect(2);
+    let statuses = tracker.accounts().collect::<Vec<_>>();
+    assert_eq!(statuses.len(), 1);
+    assert!(!statuses[0].1.healthy(start + Duration::from_secs(10)));
+}
+
+#[test]
+fn test_status_without_heartbeat() {
+    let start = Instant::now();
+    let mut tracker = StatusTracker::new(Duration::from_secs(5));
+    let status = |tracker: &StatusTracker| tracker.accounts().next().unwrap().1;
+    // connected, then silent
+    tracker.record(
+        &MetaEvent::Lifecycle {
+            self_id: 1,
+            sub_type: "connect".into(),
+        },
+        start,
+    );
+    assert!(status(&tracker).healthy(start + Duration::from_secs(15)));
+    assert!(!status(&tracker).healthy(start + Duration::from_secs(16)));
+
+    // no interval reported, so the default is kept
+    tracker.record(
+        &MetaEvent::Heartbeat {
+            self_id: 1,
+            interval: 0,
+            status: json! {{ "online": true }},
+        },
+        start + Duration::from_secs(20),
+    );
+    assert_eq!(status(&tracker).interval, Duration::from_secs(5));
+    assert!(status(&tracker).healthy(start + Duration::from_secs(21)));
+    assert!(!status(&tracker).healthy(start + Duration::from_secs(36)));
+}
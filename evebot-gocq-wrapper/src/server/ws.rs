@@ -0,0 +1,345 @@
+use crate::command::save_context;
+use crate::config::config;
+use crate::error::{BotError, BotResult};
+use crate::server::data::{Event, NoticeEvent, RequestEvent};
+use crate::server::handler::{message_reply, notice_action, request_action};
+use crate::server::{
+    track_disconnect, track_meta, verify_token, ActionResponse, MessageEvent, OneBotApi,
+};
+use actix_web::web;
+use actix_web::{HttpRequest, HttpResponse, Result};
+use actix_ws::{Message, MessageStream, Session};
+use futures_util::StreamExt;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Role of a reverse-WebSocket connection, as defined by OneBot v11.
+///
+/// - `Universal` (`/ws/`) carries both events and actions.
+/// - `Event` (`/ws/event`) only pushes events to the bot.
+/// - `Api` (`/ws/api`) only accepts actions from the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsRole {
+    Universal,
+    Event,
+    Api,
+}
+
+impl WsRole {
+    #[inline]
+    fn receives_events(&self) -> bool {
+        !matches!(self, Self::Api)
+    }
+
+    #[inline]
+    fn accepts_actions(&self) -> bool {
+        !matches!(self, Self::Event)
+    }
+}
+
+/// A call waiting for its response, with the `(self_id, conn_id)` of the connection it was
+/// sent through.
+type Pending = (u64, u64, oneshot::Sender<ActionResponse<serde_json::Value>>);
+
+/// Connections able to accept actions, keyed by the `X-Self-ID` of the account.
+///
+/// It also pairs action responses with their callers through the `echo` field.
+#[derive(Clone, Default)]
+pub struct WsRegistry {
+    sessions: Arc<Mutex<HashMap<u64, (u64, Session)>>>,
+    pending: Arc<Mutex<HashMap<String, Pending>>>,
+    counter: Arc<AtomicU64>,
+}
+
+impl WsRegistry {
+    fn register(&self, self_id: u64, session: Session) -> u64 {
+        let conn_id = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(self_id, (conn_id, session));
+        conn_id
+    }
+
+    fn unregister(&self, self_id: u64, conn_id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(&self_id).is_some_and(|(id, _)| *id == conn_id) {
+            sessions.remove(&self_id);
+        }
+        // no response will come through a closed connection, so fail its calls at once
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|_, (id, conn, _)| (*id, *conn) != (self_id, conn_id));
+    }
+
+    /// Send an action through the connection of `self_id` and wait for its response.
+    ///
+    /// The call fails at once if that connection is closed in the meantime.
+    pub async fn call(
+        &self,
+        self_id: u64,
+        action: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> BotResult<ActionResponse<serde_json::Value>> {
+        let echo = format!("evebot-{}", self.counter.fetch_add(1, Ordering::Relaxed));
+        let (tx, rx) = oneshot::channel();
+        let session = {
+            let sessions = self.sessions.lock().unwrap();
+            sessions.get(&self_id).map(|(conn_id, session)| {
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .insert(echo.clone(), (self_id, *conn_id, tx));
+                session.clone()
+            })
+        };
+        let frame = json! {{
+            "action": action,
+            "params": params,
+            "echo": echo,
+        }};
+        let sent = match session {
+            Some(mut session) => session.text(frame.to_string()).await.is_ok(),
+            None => false,
+        };
+        let err = if !sent {
+            format!("账号 {} 没有可用的 WebSocket 连接", self_id)
+        } else {
+            match tokio::time::timeout(timeout, rx).await {
+                Ok(Ok(resp)) => return Ok(resp),
+                Ok(Err(_)) => "WebSocket 连接已关闭".to_string(),
+                Err(_) => format!("等待 {} 的响应超时", action),
+            }
+        };
+        self.pending.lock().unwrap().remove(&echo);
+        Err(BotError::Action {
+            retcode: None,
+            source: err,
+        })
+    }
+
+    fn resolve(&self, resp: ActionResponse<serde_json::Value>) {
+        let Some(echo) = resp.echo.as_ref().and_then(|e| e.as_str()) else {
+            return;
+        };
+        if let Some((_, _, tx)) = self.pending.lock().unwrap().remove(echo) {
+            let _ = tx.send(resp);
+        }
+    }
+}
+
+#[test]
+fn test_ws_registry_close() {
+    use actix_web::test::TestRequest;
+    use actix_web::FromRequest;
+    actix_web::rt::System::new().block_on(async {
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_http_parts();
+        // the response holds the other end of the session
+        let body = web::Payload::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let (_response, session, _stream) = actix_ws::handle(&req, body).unwrap();
+        let registry = WsRegistry::default();
+        let conn_id = registry.register(5050, session);
+        let call = actix_web::rt::spawn({
+            let registry = registry.clone();
+            async move {
+                registry
+                    .call(5050, "get_status", json!({}), Duration::from_secs(60))
+                    .await
+            }
+        });
+        while registry.pending.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        registry.unregister(5050, conn_id);
+        let err = tokio::time::timeout(Duration::from_secs(1), call)
+            .await
+            .expect("the call should fail once its connection is closed")
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, BotError::Action { source, .. } if source == "WebSocket 连接已关闭"));
+        assert!(registry.pending.lock().unwrap().is_empty());
+    });
+}
+
+/// Build the action that delivers `reply` back to where `data` came from.
+pub fn reply_action(data: &MessageEvent, reply: serde_json::Value) -> serde_json::Value {
+    match data.group_id {
+        Some(group_id) => json! {{
+            "action": "send_group_msg",
+            "params": {
+                "group_id": group_id,
+                "message": reply,
+            }
+        }},
+        None => json! {{
+            "action": "send_private_msg",
+            "params": {
+                "user_id": data.user_id,
+                "message": reply,
+            }
+        }},
+    }
+}
+
+#[test]
+fn test_reply_action() {
+    let mut data: MessageEvent = serde_json::from_value(json! {{
+        "time": 123,
+        "self_id": 5050,
+        "post_type": "message",
+        "message_type": "group",
+        "sub_type": "normal",
+        "message_id": 505,
+        "user_id": 1050,
+        "message": [],
+        "raw_message": "",
+        "group_id": 2333
+    }})
+    .unwrap();
+    assert_eq!(
+        reply_action(&data, json!("pong")),
+        json! {{
+            "action": "send_group_msg",
+            "params": { "group_id": 2333, "message": "pong" }
+        }}
+    );
+    data.group_id = None;
+    assert_eq!(
+        reply_action(&data, json!("pong")),
+        json! {{
+            "action": "send_private_msg",
+            "params": { "user_id": 1050, "message": "pong" }
+        }}
+    );
+}
+
+pub async fn ws_universal_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    registry: web::Data<WsRegistry>,
+) -> Result<HttpResponse> {
+    ws_handler(WsRole::Universal, req, body, registry).await
+}
+
+pub async fn ws_event_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    registry: web::Data<WsRegistry>,
+) -> Result<HttpResponse> {
+    ws_handler(WsRole::Event, req, body, registry).await
+}
+
+pub async fn ws_api_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    registry: web::Data<WsRegistry>,
+) -> Result<HttpResponse> {
+    ws_handler(WsRole::Api, req, body, registry).await
+}
+
+async fn ws_handler(
+    role: WsRole,
+    req: HttpRequest,
+    body: web::Payload,
+    registry: web::Data<WsRegistry>,
+) -> Result<HttpResponse> {
+    let self_id = req
+        .headers()
+        .get("X-Self-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if let Err(err) = verify_token(req.headers(), &config().server.access_token) {
+        return Ok(err.to_response());
+    }
+    let Some(self_id) = self_id else {
+        return Ok(HttpResponse::BadRequest().body("missing X-Self-ID header"));
+    };
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let registry = registry.get_ref().clone();
+    let conn_id = role
+        .accepts_actions()
+        .then(|| registry.register(self_id, session.clone()));
+    actix_web::rt::spawn(session_loop(
+        role, session, stream, registry, self_id, conn_id,
+    ));
+    Ok(response)
+}
+
+async fn session_loop(
+    role: WsRole,
+    mut session: Session,
+    mut stream: MessageStream,
+    registry: WsRegistry,
+    self_id: u64,
+    conn_id: Option<u64>,
+) {
+    while let Some(Ok(msg)) = stream.next().await {
+        match msg {
+            Message::Text(text) => {
+                // Action responses carry `retcode`, events never do.
+                if let Ok(resp) = serde_json::from_str(&text) {
+                    registry.resolve(resp);
+                } else if role.receives_events() {
+                    // Commands may take a while; never block the event stream on them.
+                    actix_web::rt::spawn(event_handler(text.to_string(), registry.clone()));
+                }
+            }
+            Message::Ping(bytes) if session.pong(&bytes).await.is_err() => break,
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    if let Some(conn_id) = conn_id {
+        registry.unregister(self_id, conn_id);
+    }
+    if role.receives_events() {
+        track_disconnect(self_id);
+    }
+    let _ = session.close(None).await;
+}
+
+async fn event_handler(text: String, registry: WsRegistry) {
+    // e.g. the bot's own messages
+    let Ok(event) = serde_json::from_str::<Event>(&text) else {
+        return;
+    };
+    match event {
+        Event::Message(data) => {
+            let api = OneBotApi::ws(registry, data.self_id);
+            if let Some(reply) = message_reply(data.clone()).await {
+                let sent = api.reply(&data, reply.message).await;
+                // a quote-reply to the answer refers to it by this id
+                if let (Ok(sent), Some(context)) = (sent, reply.context) {
+                    save_context(data.self_id, sent.message_id as i64, context);
+                }
+            }
+        }
+        Event::Request(request) => {
+            let self_id = match &request {
+                RequestEvent::Friend { self_id, .. } | RequestEvent::Group { self_id, .. } => {
+                    *self_id
+                }
+            };
+            let _ = request_action(&OneBotApi::ws(registry, self_id), &request).await;
+        }
+        Event::Notice(notice) => {
+            if let NoticeEvent::GroupIncrease { self_id, .. } = notice {
+                let _ = notice_action(&OneBotApi::ws(registry, self_id), &notice).await;
+            }
+        }
+        Event::Meta(meta) => track_meta(&meta),
+    }
+}
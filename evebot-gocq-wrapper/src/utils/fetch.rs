@@ -1,10 +1,6 @@
-use crate::error::{BotError, BotResult};
-use crate::metadata::LOCAL_EVE_SERVICE_PORT;
-use reqwest::Response;
-use serde::{Deserialize, Serialize};
+use crate::config::config;
+use crate::error::BotResult;
 use std::fs;
-use std::io::Write;
-use std::path::Path;
 use uuid::Uuid;
 
 pub fn random_filename() -> String {
@@ -12,102 +8,35 @@ pub fn random_filename() -> String {
     uuid.hyphenated().to_string()
 }
 
-pub async fn download_image(resp: Response, fs: &Path) -> BotResult<()> {
-    let mut file = fs::File::create(fs).map_err(<std::io::Error as Into<BotError>>::into)?;
-    let bytes = resp
-        .bytes()
-        .await
-        .map_err(<reqwest::Error as Into<BotError>>::into)?;
-    file.write_all(&bytes)
-        .map_err(<std::io::Error as Into<BotError>>::into)?;
-    Ok(())
+/// Store an image from the backend where go-cqhttp can read it, and return its URI.
+pub fn save_image(bytes: &[u8]) -> BotResult<String> {
+    let bot = &config().bot;
+    fs::create_dir_all(&bot.image_directory)?;
+    let file_name = random_filename();
+    fs::write(bot.image_path(&file_name), bytes)?;
+    Ok(bot.image_uri(&file_name))
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
-pub struct TypeItem {
-    pub type_id: usize,
-    pub type_name: String,
-    #[serde(default)]
-    pub published: bool,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TypeIDFetch<T: AsRef<str>> {
-    Absolute(T),
-    Fuzzy(T),
-    Manual(T),
-}
-
-impl<T: AsRef<str> + std::fmt::Debug> TypeIDFetch<T> {
-    pub async fn get(&self) -> BotResult<TypeItem> {
-        match self {
-            TypeIDFetch::Absolute(t) => {
-                reqwest::ClientBuilder::new()
-                    .build()?
-                    .get(format!(
-                        "http://localhost:{}/types/search/absolute/?name={}",
-                        LOCAL_EVE_SERVICE_PORT,
-                        t.as_ref()
-                    ))
-                    .send()
-                    .await?
-                    .json()
-                    .await
-            }
-            TypeIDFetch::Fuzzy(t) => {
-                reqwest::ClientBuilder::new()
-                    .build()?
-                    .get(format!(
-                        "http://localhost:{}/types/search/fuzzy/?name={}",
-                        LOCAL_EVE_SERVICE_PORT,
-                        t.as_ref()
-                    ))
-                    .send()
-                    .await?
-                    .json()
-                    .await
-            }
-            TypeIDFetch::Manual(p) => {
-                reqwest::ClientBuilder::new()
-                    .build()?
-                    .get(format!(
-                        "http://localhost:{}/types/search/manual/?pattern={}",
-                        LOCAL_EVE_SERVICE_PORT,
-                        p.as_ref()
-                    ))
-                    .send()
-                    .await?
-                    .json()
-                    .await
-            }
-        }
-        .map_err(|e| e.into())
-    }
-
-    pub fn type_from(val: impl AsRef<str>) -> Box<dyn FnOnce(T) -> BotResult<Self>> {
-        match val.as_ref().to_ascii_lowercase().as_str() {
-            "absolute" | "a" | "abs" => Box::new(|v| Ok(Self::Absolute(v))),
-            "fuzzy" | "f" | "fuz" | "fuzz" => Box::new(|v| Ok(Self::Fuzzy(v))),
-            "manual" | "m" | "man" => Box::new(|v| Ok(Self::Manual(v))),
-            _ => {
-                let val = val.as_ref().to_owned();
-                Box::new(move |_| {
-                    Err(BotError::Syntax {
-                        found: Some(val),
-                        expected: Some("choices: [a]bsolute, [f]uzzy, [m]anual.".to_string()),
-                        note: Some("use a valid fetch pattern type.".to_string()),
-                    })
-                })
-            }
-        }
-    }
+/// The content of the image answered by a command with the mock backend. The file is removed.
+#[cfg(test)]
+pub fn take_image(message: &crate::server::MessageChain) -> String {
+    use crate::server::Segment;
+    let Some(Segment::Image { file, .. }) = message.segments().first() else {
+        panic!("not an image: {:?}", message);
+    };
+    let path = file.strip_prefix("file://").unwrap();
+    let content = fs::read_to_string(path).unwrap();
+    fs::remove_file(path).unwrap();
+    content
 }
 
+/// Find the type the user meant, or the one they chose, see [`crate::command::chosen_type`].
+///
 /// # Syntax
 ///
 /// ```
 /// fetch_type! {
+///     backend: backend_value,
 ///     pattern: pattern_value,
 ///     type_name: type_name_value,
 ///     error: error_ident,
@@ -115,19 +44,27 @@ impl<T: AsRef<str> + std::fmt::Debug> TypeIDFetch<T> {
 /// ```
 #[macro_export]
 macro_rules! fetch_type {
-    {pattern: $pat: expr, type_name: $tn: expr, error: $err: ident $(,)?} => {
-        match $crate::utils::fetch::TypeIDFetch::type_from($pat)($tn) {
-            Ok(data) => match data.get().await {
-                Ok(val) => Some(val),
+    {backend: $backend: expr, pattern: $pat: expr, type_name: $tn: expr, error: $err: ident $(,)?} => {
+        match $crate::command::chosen_type() {
+            Some(val) => Some(val),
+            None => match $crate::backend::TypeIDFetch::type_from($pat)($tn)
+                .and_then($crate::command::allow_search)
+            {
+                Ok(data) => match $crate::backend::pick_type($backend, data.as_deref()).await {
+                    Ok(val) => {
+                        $crate::command::resolved(&val);
+                        Some(val)
+                    }
+                    Err(err) => {
+                        $err.push(err);
+                        None
+                    }
+                },
                 Err(err) => {
                     $err.push(err);
                     None
                 }
             },
-            Err(err) => {
-                $err.push(err);
-                None
-            }
         }
     };
 }
@@ -1,4 +1,4 @@
 pub mod fetch;
-pub mod json_wrapper;
 pub mod numeric;
 pub mod pattern_match;
+pub mod suggest;
@@ -8,12 +8,35 @@ macro_rules! static_match {
     };
 }
 
+/// Run the parse result `$val` against `$backend` unless `$throttle(&$limit)` refuses it, and
+/// evaluate to the answer, or `None` to stay silent. A search with several candidates is answered
+/// with a question, see [`crate::command::Pending::ask`].
 #[macro_export]
 macro_rules! get_content {
-    ($val: expr) => {
+    ($val: expr, $backend: expr, $throttle: ident, $limit: expr) => {{
+        const _LIMIT: $crate::command::CommandLimit = $limit;
         match $val {
-            Ok(_v) => _v.get_content().await,
-            Err(_e) => Err(_e),
+            Ok(_v) => match $throttle(&_LIMIT) {
+                Ok(()) => Some(
+                    match $crate::command::resolving(_v.get_content($backend)).await {
+                        // a follow-up keeps the type found, instead of searching again
+                        (Ok(_message), _item) => Ok($crate::command::CommandOutput {
+                            message: _message,
+                            context: _v
+                                .into_context()
+                                .map(|_c| _LIMIT.wrap($crate::command::with_type(_c, _item))),
+                            pending: None,
+                        }),
+                        (Err(_e), _) => $crate::command::Pending::ask(
+                            _LIMIT.wrap(::std::sync::Arc::new(_v)),
+                            &_e,
+                        )
+                        .ok_or(_e),
+                    },
+                ),
+                Err(_notice) => _notice.map(|_m| Ok(_m.into())),
+            },
+            Err(_e) => Some(Err(_e)),
         }
-    };
+    }};
 }
@@ -0,0 +1,61 @@
+//! "Did you mean" suggestions for mistyped names, used by the generated parsers and distributor.
+
+/// Edit distance where insertions, deletions, substitutions and swaps of two adjacent
+/// characters all cost 1.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows `i - 2`, `i - 1` and `i` of the distance table
+    let mut prev2: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr: Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                curr[j] = curr[j].min(prev2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// The candidate closest to `input`, if it is close enough to be a plausible typo.
+pub fn did_you_mean<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    let limit = (input.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|&c| (edit_distance(&input, &c.to_lowercase()), c))
+        .filter(|&(d, c)| d <= limit && d < c.chars().count())
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// `"，是否是 xxx？"` if there is a suggestion, to be appended to an error note.
+pub fn hint(input: &str, candidates: &[&str]) -> String {
+    did_you_mean(input, candidates)
+        .map(|s| format!("，是否是 {}？", s))
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_did_you_mean() {
+    assert_eq!(edit_distance("exapnd", "expand"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+    let candidates = ["expand", "exp", "e", "manu_mat_level", "mm"];
+    assert_eq!(did_you_mean("exapnd", &candidates), Some("expand"));
+    assert_eq!(
+        did_you_mean("manu_mat_levle", &candidates),
+        Some("manu_mat_level")
+    );
+    assert_eq!(did_you_mean("x", &candidates), None);
+    assert_eq!(did_you_mean("server", &candidates), None);
+    assert_eq!(hint("EXPANd", &candidates), "，是否是 expand？");
+}
@@ -2,50 +2,177 @@ use itertools::Itertools;
 use proc_macro::TokenStream as RawTokenStream;
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
-use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize};
 use syn::{ExprPath, LitStr};
 
-type IResult<T> = Result<T, TokenStream>;
-
 pub fn create_distributor(input: RawTokenStream) -> RawTokenStream {
-    match create_dis(input) {
-        Ok(token) | Err(token) => {
-            // std::fs::write(
-            //     "./macro-expand/test.output.distributor.rs",
-            //     format!(
-            //         "{}",
-            //         quote! { pub fn test() {
-            //             #token
-            //         } }
-            //     ),
-            // )
-            // .unwrap();
-            token.into()
-        }
+    let token = create_dis(input).unwrap_or_else(|e| e.to_compile_error());
+    // std::fs::write(
+    //     "./macro-expand/test.output.distributor.rs",
+    //     format!(
+    //         "{}",
+    //         quote! { pub fn test() {
+    //             #token
+    //         } }
+    //     ),
+    // )
+    // .unwrap();
+    token.into()
+}
+
+/// Where the configuration came from, for error reporting.
+struct Source<'a> {
+    lit: &'a LitStr,
+    label: &'a str,
+}
+
+impl Source<'_> {
+    fn error(&self, key: &str, msg: impl std::fmt::Display) -> syn::Error {
+        syn::Error::new(
+            self.lit.span(),
+            format!("`{}`: `{}`: {}", self.label, key, msg),
+        )
     }
 }
 
-fn create_dis(input: RawTokenStream) -> IResult<TokenStream> {
-    let lit_fp = syn::parse::<LitStr>(input).map_err(|e| e.to_compile_error())?;
-    let data = get_config_data(&lit_fp);
+fn create_dis(input: RawTokenStream) -> syn::Result<TokenStream> {
+    let lit_fp = syn::parse::<LitStr>(input)?;
+    let label = lit_fp.value();
+    let fp = crate::path::resolve(&label);
+    let data: Vec<Node> = crate::path::read_json(&fp, &label, &lit_fp)?;
+    let source = Source {
+        lit: &lit_fp,
+        label: &label,
+    };
+    let tracker = crate::path::track([&fp]);
     let param_ident = Ident::new("param", Span::call_site());
-    let grp_it = data.iter().map(|s| s.to_pattern(&param_ident));
-    Ok(quote! {
-        match &#param_ident.next()? {
-            crate::server::ParamItem::Text(_t) => match _t.as_str() {
+    let (grp_it, errors): (Vec<_>, Vec<_>) = data
+        .iter()
+        .map(|s| s.to_pattern(&param_ident, &source, ""))
+        .partition_result();
+    crate::path::combine(errors)?;
+    // `group_enabled` is expected at the call site, like `param`
+    let disabled = data.iter().map(|node| {
+        let ident = Literal::string(node.ident());
+        let names = node.names().map(Literal::string);
+        quote! { #(#names)|* if !group_enabled(#ident) => None, }
+    });
+    // a disabled node is never suggested, as it would not answer either
+    let candidates = data.iter().map(|node| {
+        let ident = Literal::string(node.ident());
+        let names = node.names().map(Literal::string);
+        quote! { (#ident, &[#(#names),*] as &[&str]) }
+    });
+    // not every message starting with a prefix is meant for the bot, so stay quiet without a match
+    let fallback = suggestion(quote! { &__names }, quote! { None });
+    Ok(quote! {{
+        #tracker
+        match #param_ident.next()? {
+            crate::server::Token {
+                item: crate::server::ParamItem::Text(_t),
+                span: _span,
+            } => match _t.as_str() {
+                #(#disabled)*
                 #(#grp_it)*
-                _ => None
+                _ => {
+                    let __names: ::std::vec::Vec<&str> = [#(#candidates),*]
+                        .into_iter()
+                        .filter(|(_ident, _)| group_enabled(_ident))
+                        .flat_map(|(_, _names)| _names.iter().copied())
+                        .collect();
+                    #fallback
+                }
             },
             _ => None
         }
-    })
+    }})
+}
+
+/// Report `_t` (found at `_span`) as an unknown command if it looks like a typo of one of `names`,
+/// and evaluate to `otherwise` if not.
+fn suggestion(names: TokenStream, otherwise: TokenStream) -> TokenStream {
+    quote! {
+        match crate::utils::suggest::did_you_mean(_t.as_str(), #names) {
+            Some(_name) => Some(Err(crate::error::BotError::Syntax {
+                found: Some(_t.to_string()),
+                expected: Some(_name.to_owned()),
+                note: Some(format!("未知的命令，是否是 {}？", _name)),
+                span: _span,
+            }
+            .into())),
+            None => #otherwise,
+        }
+    }
+}
+
+/// A top-level entry: a group of commands, or a command of its own such as `eve status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+enum Node {
+    Command(SubCommand),
+    Group(SubGroup),
+}
+
+impl<'de> Deserialize<'de> for Node {
+    /// Pick the variant by the keys only groups have, rather than trying both, so that the
+    /// errors of a malformed entry name the offending field.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let is_group = ["group_name", "group-name", "subcommand", "subgroup"]
+            .iter()
+            .any(|key| value.get(key).is_some());
+        if is_group {
+            SubGroup::deserialize(value).map(Self::Group)
+        } else {
+            SubCommand::deserialize(value).map(Self::Command)
+        }
+        .map_err(serde::de::Error::custom)
+    }
 }
 
-fn get_config_data(lit_fp: &LitStr) -> Vec<SubGroup> {
-    let fp = lit_fp.value();
-    let content = std::fs::read(fp).unwrap();
-    serde_json::from_slice(&content).unwrap()
+#[test]
+fn test_node_errors() {
+    let parse = |json: &str| serde_json::from_str::<Vec<Node>>(json).map_err(|e| e.to_string());
+    let nodes = parse(
+        r#"[
+            { "path_ident": "status", "structure-path": "a::B", "description": "" },
+            { "path_ident": "blp", "description": "", "group_name": "Blp", "subcommand": [] }
+        ]"#,
+    )
+    .unwrap();
+    assert!(matches!(nodes[..], [Node::Command(_), Node::Group(_)]));
+    let err = parse(r#"[{ "structure-path": "a::B", "description": "" }]"#).unwrap_err();
+    assert!(err.contains("missing field `path_ident`"), "{}", err);
+    let err = parse(r#"[{ "path_ident": "blp", "description": "", "subgroup": [] }]"#).unwrap_err();
+    assert!(err.contains("missing field `group_name`"), "{}", err);
+}
+
+impl Node {
+    fn to_pattern(
+        &self,
+        param_ident: &Ident,
+        source: &Source<'_>,
+        parent_key: &str,
+    ) -> syn::Result<TokenStream> {
+        match self {
+            Self::Command(c) => c.to_pattern(param_ident, source, parent_key),
+            Self::Group(g) => g.to_pattern(param_ident, source, parent_key),
+        }
+    }
+
+    fn ident(&self) -> &str {
+        match self {
+            Self::Command(c) => &c.path_ident,
+            Self::Group(g) => &g.path_ident,
+        }
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        match self {
+            Self::Command(c) => itertools::Either::Left(c.names()),
+            Self::Group(g) => itertools::Either::Right(g.names()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,54 +191,72 @@ struct SubGroup {
 }
 
 impl SubGroup {
-    fn to_pattern(&self, param_ident: &Ident) -> TokenStream {
+    fn to_pattern(
+        &self,
+        param_ident: &Ident,
+        source: &Source<'_>,
+        parent_key: &str,
+    ) -> syn::Result<TokenStream> {
+        let key = format!("{}{}", parent_key, self.path_ident);
         let help_text = Literal::string(&self.to_help());
-        let subcommand_matcher = {
-            let _it = self
-                .subcommand
-                .iter()
-                .flatten()
-                .map(|s| match s.to_pattern(param_ident) {
-                    Ok(v) | Err(v) => v,
-                });
-            quote! {
-                #(#_it)*
-            }
+        let (subcommands, subcommand_errors): (Vec<_>, Vec<_>) = self
+            .subcommand
+            .iter()
+            .flatten()
+            .map(|s| s.to_pattern(param_ident, source, &format!("{}.", key)))
+            .partition_result();
+        let (subgroups, subgroup_errors): (Vec<_>, Vec<_>) = self
+            .subgroup
+            .iter()
+            .flatten()
+            .map(|s| s.to_pattern(param_ident, source, &format!("{}.", key)))
+            .partition_result();
+        crate::path::combine(subcommand_errors.into_iter().chain(subgroup_errors))?;
+        let subcommand_matcher = quote! {
+            #(#subcommands)*
         };
-        let subgroup_matcher = {
-            let _it = self
-                .subgroup
-                .iter()
-                .flatten()
-                .map(|s| s.to_pattern(param_ident));
-            quote! {
-                #(#_it)*
-            }
-        };
-        let path_pattern = {
-            let _list = [self.path_ident.to_owned()];
-            let val = _list
-                .iter()
-                .chain(self.path_alias.iter().flatten())
-                .map(|s| Literal::string(s));
-            quote! {
-                #(#val)|*
-            }
+        let subgroup_matcher = quote! {
+            #(#subgroups)*
         };
+        let path_pattern = self.names().map(Literal::string);
+        let names = self
+            .subcommand
+            .iter()
+            .flatten()
+            .flat_map(SubCommand::names)
+            .chain(self.subgroup.iter().flatten().flat_map(SubGroup::names))
+            .map(Literal::string);
+        let fallback = suggestion(
+            quote! { __NAMES },
+            quote! { Some(Ok(crate::server::MessageChain::new().text(#help_text).into())) },
+        );
 
-        quote! {
-            #path_pattern => match #param_ident.next() {
+        Ok(quote! {
+            #(#path_pattern)|* => match #param_ident.next() {
                 Some(_ident) => match _ident {
-                    crate::server::ParamItem::Text(_t) => match _t.as_str() {
+                    crate::server::Token {
+                        item: crate::server::ParamItem::Text(_t),
+                        span: _span,
+                    } => match _t.as_str() {
                         #subcommand_matcher
                         #subgroup_matcher
-                        _ => Some(Ok(crate::build_single_text!(#help_text)))
+                        _ => {
+                            const __NAMES: &[&str] = &[#(#names),*];
+                            #fallback
+                        }
                     },
                     _ => None
                 },
-                _ => Some(Ok(crate::build_single_text!(#help_text)))
+                _ => Some(Ok(crate::server::MessageChain::new().text(#help_text).into()))
             },
-        }
+        })
+    }
+
+    /// The ident followed by its aliases.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        [self.path_ident.as_str()]
+            .into_iter()
+            .chain(self.path_alias.iter().flatten().map(String::as_str))
     }
 
     fn to_help(&self) -> String {
@@ -166,29 +311,80 @@ struct SubCommand {
     #[serde(alias = "no-help", default)]
     no_help: bool,
     description: String,
+    /// Tokens taken from the rate limit buckets.
+    #[serde(default = "default_cost")]
+    cost: u32,
+    /// Seconds before the same user can run it again.
+    #[serde(default)]
+    cooldown: u64,
+    /// Only run for the users `admin()` allows.
+    #[serde(default)]
+    admin: bool,
+}
+
+fn default_cost() -> u32 {
+    1
 }
 
 impl SubCommand {
-    fn to_pattern(&self, param_ident: &Ident) -> IResult<TokenStream> {
-        let raw_path = TokenStream::from_str(&self.structure_path).unwrap();
-        let structure_path =
-            syn::parse::<ExprPath>(raw_path.into()).map_err(|e| e.to_compile_error())?;
-        let path_pattern = [self.path_ident.to_owned()]
+    /// The ident followed by its aliases.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        [self.path_ident.as_str()]
             .into_iter()
-            .chain(self.path_alias.iter().flatten().cloned())
-            .map(|s| Literal::string(&s));
+            .chain(self.path_alias.iter().flatten().map(String::as_str))
+    }
 
+    fn to_pattern(
+        &self,
+        param_ident: &Ident,
+        source: &Source<'_>,
+        parent_key: &str,
+    ) -> syn::Result<TokenStream> {
+        let key = format!("{}{}.structure-path", parent_key, self.path_ident);
+        let structure_path = syn::parse_str::<ExprPath>(&self.structure_path).map_err(|e| {
+            source.error(
+                &key,
+                format!("invalid structure path `{}`: {}", self.structure_path, e),
+            )
+        })?;
+        let path_pattern = self.names().map(Literal::string);
+        let command = Literal::string(&format!("{}{}", parent_key, self.path_ident));
+        let cost = Literal::u32_unsuffixed(self.cost);
+        let cooldown = Literal::u64_unsuffixed(self.cooldown);
+        // `backend` and `throttle` are expected at the call site, like `param`
+        let content = quote! {
+            crate::get_content!(
+                #structure_path::get_result(#param_ident),
+                backend,
+                throttle,
+                crate::command::CommandLimit {
+                    command: #command,
+                    cost: #cost,
+                    cooldown: #cooldown,
+                }
+            )
+        };
+
+        // `admin` is expected at the call site too
+        let admin = if self.admin {
+            let names = self.names().map(Literal::string);
+            quote! { #(#names)|* if !admin() => None, }
+        } else {
+            quote! {}
+        };
         if self.no_help {
             Ok(quote! {
-                #(#path_pattern)|* => crate::get_content!(#structure_path::get_result(#param_ident))
+                #admin
+                #(#path_pattern)|* => #content,
             })
         } else {
             Ok(quote! {
-                #(#path_pattern)|* => Some(if #param_ident.peek().is_some() {
-                    crate::get_content!(#structure_path::get_result(#param_ident))
+                #admin
+                #(#path_pattern)|* => if #param_ident.peek().is_some() {
+                    #content
                 } else {
-                    Ok(crate::build_single_text!(#structure_path::SYNTAX_TEXT))
-                }),
+                    Some(Ok(crate::server::MessageChain::new().text(#structure_path::SYNTAX_TEXT).into()))
+                },
             })
         }
     }
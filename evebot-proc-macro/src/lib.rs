@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 
 mod distributor;
+mod path;
 mod syntax_macro;
 
 #[proc_macro_attribute]
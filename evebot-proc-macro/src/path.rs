@@ -0,0 +1,64 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use syn::LitStr;
+
+/// Resolve a path from a macro input against the manifest directory of the invoking crate,
+/// so expansion does not depend on where cargo was started from.
+pub fn resolve(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if path.is_absolute() {
+        return path.to_owned();
+    }
+    match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir).join(path),
+        None => path.to_owned(),
+    }
+}
+
+/// Register files as inputs of the invoking crate, so editing them triggers recompilation.
+pub fn track<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> TokenStream {
+    let paths = paths
+        .into_iter()
+        .map(|p| Literal::string(&p.to_string_lossy()));
+    quote! {
+        #(const _: &[u8] = include_bytes!(#paths);)*
+    }
+}
+
+/// Read and deserialize a JSON file, reporting failures at `lit` with the file name,
+/// and the line and column for malformed JSON.
+pub fn read_json<T: DeserializeOwned>(fp: &Path, label: &str, lit: &LitStr) -> syn::Result<T> {
+    let content = std::fs::read(fp).map_err(|e| {
+        syn::Error::new(
+            lit.span(),
+            format!("cannot read `{}` ({}): {}", label, fp.display(), e),
+        )
+    })?;
+    serde_json::from_slice(&content).map_err(|e| {
+        let suffix = format!(" at line {} column {}", e.line(), e.column());
+        let msg = e.to_string();
+        syn::Error::new(
+            lit.span(),
+            format!(
+                "`{}`:{}:{}: {}",
+                label,
+                e.line(),
+                e.column(),
+                msg.strip_suffix(&suffix).unwrap_or(&msg)
+            ),
+        )
+    })
+}
+
+/// Merge several errors into one, so that all of them are reported at once.
+pub fn combine(errors: impl IntoIterator<Item = syn::Error>) -> syn::Result<()> {
+    match errors.into_iter().reduce(|mut acc, e| {
+        acc.combine(e);
+        acc
+    }) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
@@ -1,10 +1,12 @@
 use itertools::Itertools;
 use proc_macro::TokenStream as RawTokenStream;
 use proc_macro2::{Ident, Literal, Span, TokenStream};
-use quote::quote;
+use quote::{quote, ToTokens};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use syn::ItemStruct;
+use std::path::PathBuf;
+use syn::{ItemStruct, LitStr};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServiceSyntax {
@@ -35,7 +37,7 @@ impl Display for ServiceSyntax {
             } else {
                 self.positional_args
                     .iter()
-                    .map(|s| format!("[{}]", s.arg_name))
+                    .map(PositionalArg::tag)
                     .join(", ")
                     + " "
             },
@@ -62,17 +64,388 @@ impl Display for ServiceSyntax {
 }
 
 impl ServiceSyntax {
-    fn process_mixin(mut self) -> Self {
-        let mixins = self.mixin.iter().map(|s| {
-            serde_json::from_slice::<'_, Self>(&std::fs::read(&s).unwrap_or_else(print_fs_err(&s)))
-                .unwrap()
-                .process_mixin()
+    /// Load a syntax file and merge all its mixins into it, recording every file read into `files`.
+    ///
+    /// Each argument remembers the file it was declared in, for error reporting.
+    fn load(label: String, lit: &LitStr, files: &mut Vec<PathBuf>) -> syn::Result<Self> {
+        let fp = crate::path::resolve(&label);
+        if files.contains(&fp) {
+            return Err(syn::Error::new(
+                lit.span(),
+                format!("`{}`: mixin is included more than once", label),
+            ));
+        }
+        let mut data: Self = crate::path::read_json(&fp, &label, lit)?;
+        files.push(fp);
+        for arg in &mut data.positional_args {
+            arg.source.clone_from(&label);
+        }
+        for arg in &mut data.param_args {
+            arg.source.clone_from(&label);
+            // a flag that is not given is off
+            if matches!(arg.arg_type, ArgType::Flag) && arg.default.is_none() {
+                arg.default = Some(ArgValue::Boolean(false));
+            }
+        }
+        for mixin in std::mem::take(&mut data.mixin) {
+            let mut mixin = Self::load(mixin, lit, files)?;
+            data.param_args.append(&mut mixin.param_args);
+            data.positional_args.append(&mut mixin.positional_args);
+        }
+        Ok(data)
+    }
+
+    /// Check everything that would otherwise surface as a panic or a confusing error
+    /// in the generated code.
+    fn validate(&self, lit: &LitStr) -> syn::Result<()> {
+        let error = |source: &str, key: &str, msg: String| {
+            syn::Error::new(lit.span(), format!("`{}`: `{}`: {}", source, key, msg))
+        };
+        let mut errors = vec![];
+        // name or alias -> `file: key` of its first declaration
+        let mut names: HashMap<&str, String> = HashMap::new();
+        let args = self
+            .positional_args
+            .iter()
+            .map(|s| {
+                (
+                    "positional_args",
+                    &s.arg_name,
+                    &s.source,
+                    &s.arg_type,
+                    &s.constraint,
+                    None,
+                    None,
+                )
+            })
+            .chain(self.param_args.iter().map(|s| {
+                (
+                    "param_args",
+                    &s.arg_name,
+                    &s.source,
+                    &s.arg_type,
+                    &s.constraint,
+                    s.default.as_ref(),
+                    s.alias.as_ref(),
+                )
+            }));
+        for (kind, name, source, arg_type, constraint, default, alias) in args {
+            let key = format!("{}.{}", kind, name);
+            if syn::parse_str::<Ident>(name).is_err() {
+                errors.push(error(
+                    source,
+                    &format!("{}.arg_name", key),
+                    format!("`{}` is not a valid identifier", name),
+                ));
+            }
+            let aliases = alias
+                .iter()
+                .flat_map(|s| s.iter())
+                .enumerate()
+                .map(|(idx, s)| (s, format!("{}.alias[{}]", key, idx)));
+            for (name, name_key) in [(name, format!("{}.arg_name", key))]
+                .into_iter()
+                .chain(aliases)
+            {
+                if let Some(first) = names.get(name.as_str()) {
+                    errors.push(error(
+                        source,
+                        &name_key,
+                        format!("`{}` is already declared at {}", name, first),
+                    ));
+                } else {
+                    names.insert(name, format!("`{}`: `{}`", source, name_key));
+                }
+            }
+            if let Err(msg) = arg_type.validate() {
+                errors.push(error(source, &format!("{}.arg_type", key), msg));
+            }
+            if let Some(Err(msg)) = default.map(|d| d.check_type(arg_type)) {
+                errors.push(error(source, &format!("{}.default", key), msg));
+            } else if let Some(Err(msg)) = default.map(|d| constraint.check_default(d)) {
+                errors.push(error(source, &format!("{}.default", key), msg));
+            }
+            for msg in constraint.validate(arg_type) {
+                errors.push(error(source, &key, msg));
+            }
+        }
+        let last = self.positional_args.len().saturating_sub(1);
+        for (idx, s) in self.positional_args.iter().enumerate() {
+            let key = format!("positional_args.{}", s.arg_name);
+            if s.greedy && s.variadic {
+                errors.push(error(
+                    &s.source,
+                    &key,
+                    "`greedy` and `variadic` are exclusive".into(),
+                ));
+            }
+            if (s.greedy || s.variadic) && idx != last {
+                errors.push(error(
+                    &s.source,
+                    &key,
+                    "only the last positional argument can be greedy or variadic".into(),
+                ));
+            }
+            if let ArgType::Flag = s.arg_type {
+                errors.push(error(
+                    &s.source,
+                    &format!("{}.arg_type", key),
+                    "FLAG can only be a param arg".into(),
+                ));
+            }
+            if s.greedy && !s.arg_type.is_textual() {
+                errors.push(error(
+                    &s.source,
+                    &key,
+                    format!("`greedy` is not applicable to {}", s.arg_type),
+                ));
+            }
+        }
+        crate::path::combine(errors)
+    }
+
+    /// `into_context` and the `FollowUp` impl, which runs the command again once the user
+    /// chose a type. If any parameter is marked `follow_up`, also the `apply_follow_up` method
+    /// to re-run it with changed parameters.
+    fn to_follow_up(&self, result_struct_type: &Ident) -> TokenStream {
+        let args = self
+            .param_args
+            .iter()
+            .filter(|s| s.follow_up)
+            .collect::<Vec<_>>();
+        let run = quote! {
+            fn run<'a>(
+                &'a self,
+                backend: &'a dyn crate::backend::EveBackend,
+            ) -> ::std::pin::Pin<
+                Box<
+                    dyn ::std::future::Future<
+                            Output = crate::error::BotGroupResult<crate::server::MessageChain>,
+                        > + 'a,
+                >,
+            > {
+                Box::pin(self.get_content(backend))
+            }
+        };
+        if args.is_empty() {
+            return quote! {
+                impl #result_struct_type {
+                    #[allow(dead_code)]
+                    pub fn into_context(self) -> Option<crate::command::Context> {
+                        None
+                    }
+                }
+
+                impl crate::command::FollowUp for #result_struct_type {
+                    fn follow_up(
+                        &self,
+                        _: ::std::vec::Vec<crate::server::Token>,
+                    ) -> Option<crate::error::BotGroupResult<crate::command::Context>> {
+                        None
+                    }
+
+                    #run
+                }
+            };
+        }
+        let result_ident = Ident::new("_result_group", Span::call_site());
+        let follow_ident = Ident::new("__follow_up", Span::call_site());
+        let keys_ident = Ident::new("__FOLLOW_UP_KEYS", Span::call_site());
+        let keys = args
+            .iter()
+            .flat_map(|s| s.keys())
+            .map(|(key, _)| Literal::string(&key));
+        let matcher = args.iter().flat_map(|s| {
+            let name = Literal::string(&s.arg_name);
+            s.keys()
+                .map(|(key, flag)| {
+                    let key = Literal::string(&key);
+                    let flag = match flag {
+                        Some(on) => quote! { Some(#on) },
+                        None => quote! { None },
+                    };
+                    quote! { #key => Some((#name, #flag)) }
+                })
+                .collect::<Vec<_>>()
         });
-        for mut mixin in mixins {
-            self.param_args.append(&mut mixin.param_args);
-            self.positional_args.append(&mut mixin.positional_args);
+        let next_value = next_value(
+            args.iter().copied(),
+            quote! { _name },
+            quote! { |_t: &str| #keys_ident.contains(&_t) },
+        );
+        // a bare value is taken for the one parameter that accepts it, like `tq` for a server
+        let candidates = args
+            .iter()
+            .filter(|s| {
+                !matches!(
+                    s.arg_type,
+                    ArgType::Flag | ArgType::AnyText | ArgType::Mention
+                )
+            })
+            .map(|s| {
+                let name = Literal::string(&s.arg_name);
+                let parser = s.arg_type.text_parser(quote! { _text.as_str() });
+                quote! {
+                    if (#parser).is_some() {
+                        _candidates.push(#name);
+                    }
+                }
+            });
+        let apply = args.iter().map(|s| {
+            let name = Literal::string(&s.arg_name);
+            let name_lit = Literal::string(&format!("{} [{}]", &s.arg_name, &s.arg_type));
+            let name_ident = Ident::new(&format!("__result_{}", &s.arg_name), Span::call_site());
+            let field = Ident::new(&s.arg_name, Span::call_site());
+            let parser = s.arg_type.to_parser(ArgTypeParser {
+                val_ident: &Ident::new("_val", Span::call_site()),
+                result_ident: &result_ident,
+                name_lit: &name_lit,
+            });
+            let ret_type = s.arg_type.as_return_type();
+            let check = s.constraint.to_check(
+                &s.arg_type,
+                false,
+                &name_ident,
+                &result_ident,
+                &name_lit,
+                &quote! { _val.span },
+            );
+            quote! {
+                #name => {
+                    let #name_ident: Option<#ret_type> = #parser;
+                    #check
+                    if let Some(_x) = #name_ident {
+                        #follow_ident.#field = _x.into();
+                    }
+                }
+            }
+        });
+        let syntax_error = |found: TokenStream, expected: TokenStream, note: &str| {
+            quote! {
+                #result_ident.push(crate::error::BotError::Syntax {
+                    found: Some(#found),
+                    expected: #expected,
+                    note: Some(#note.to_string()),
+                    span: _item.span,
+                })
+            }
+        };
+        let extra_error = syntax_error(
+            quote! { format!("{}", _item) },
+            quote! { None },
+            "多余的参数",
+        );
+        let dangling_error = syntax_error(
+            quote! { _text.to_owned() },
+            quote! { Some(format!("{} 的取值", _name)) },
+            "参数缺少取值",
+        );
+        let unknown_error = syntax_error(
+            quote! { _text.to_owned() },
+            quote! { crate::utils::suggest::did_you_mean(&_text, #keys_ident).map(ToOwned::to_owned) },
+            "无法追加的参数",
+        );
+        let ambiguous_error = syntax_error(
+            quote! { _text.to_owned() },
+            quote! { Some(_candidates.join(" / ")) },
+            "无法确定要修改的参数，请写明参数名",
+        );
+        quote! {
+            impl #result_struct_type {
+                #[allow(dead_code)]
+                pub fn into_context(self) -> Option<crate::command::Context> {
+                    Some(::std::sync::Arc::new(self))
+                }
+
+                /// A copy of `self` with the follow-up parameters in `param` applied, or `None`
+                /// if `param` does not start with one.
+                fn apply_follow_up<T, I>(&self, param: ::std::iter::Peekable<T>)
+                    -> Option<crate::error::BotGroupResult<#result_struct_type>>
+                where
+                    T: Iterator<Item = I>,
+                    I: Into<crate::server::Token>,
+                {
+                    const #keys_ident: &[&str] = &[#(#keys),*];
+                    let mut param = param.map(Into::<crate::server::Token>::into).peekable();
+                    let mut #result_ident = crate::error::BotErrorGroup::new();
+                    let mut #follow_ident = self.clone();
+                    let mut _first = true;
+                    while let Some(_item) = param.next() {
+                        let _first_item = ::std::mem::replace(&mut _first, false);
+                        let crate::server::ParamItem::Text(_text) = &_item.item else {
+                            if _first_item {
+                                return None;
+                            }
+                            #extra_error;
+                            continue;
+                        };
+                        let _key: Option<(&str, Option<bool>)> = match _text.as_str() {
+                            #(#matcher,)*
+                            _ => None,
+                        };
+                        let (_name, _val) = match _key {
+                            Some((_name, Some(_on))) => (_name, crate::server::Token {
+                                item: crate::server::ParamItem::Text(_on.to_string()),
+                                span: _item.span,
+                            }),
+                            Some((_name, None)) => match #next_value {
+                                Some(_val) => (_name, _val),
+                                None => {
+                                    #dangling_error;
+                                    continue;
+                                }
+                            },
+                            None => {
+                                #[allow(unused_mut)]
+                                let mut _candidates: ::std::vec::Vec<&str> = ::std::vec::Vec::new();
+                                #(#candidates)*
+                                match _candidates.as_slice() {
+                                    [_name] => (*_name, _item.clone()),
+                                    _ if _first_item => return None,
+                                    [] => {
+                                        #unknown_error;
+                                        // most likely the value of the unknown key
+                                        param.next_if(|_v| {
+                                            !matches!(&_v.item, crate::server::ParamItem::Text(_t) if #keys_ident.contains(&_t.as_str()))
+                                        });
+                                        continue;
+                                    }
+                                    _ => {
+                                        #ambiguous_error;
+                                        continue;
+                                    }
+                                }
+                            }
+                        };
+                        match _name {
+                            #(#apply)*
+                            _ => {}
+                        }
+                    }
+                    if _first {
+                        None
+                    } else if #result_ident.as_ref().is_empty() {
+                        Some(Ok(#follow_ident))
+                    } else {
+                        Some(Err(#result_ident))
+                    }
+                }
+            }
+
+            impl crate::command::FollowUp for #result_struct_type {
+                fn follow_up(
+                    &self,
+                    param: ::std::vec::Vec<crate::server::Token>,
+                ) -> Option<crate::error::BotGroupResult<crate::command::Context>> {
+                    Some(
+                        self.apply_follow_up(param.into_iter().peekable())?
+                            .map(|_v| ::std::sync::Arc::new(_v) as crate::command::Context),
+                    )
+                }
+
+                #run
+            }
         }
-        self
     }
 
     fn to_parser(&self, struct_name: &Ident) -> TokenStream {
@@ -81,11 +454,14 @@ impl ServiceSyntax {
             let val = self
                 .positional_args
                 .iter()
-                .map(|s| (&s.arg_name, &s.arg_type))
-                .chain(self.param_args.iter().map(|s| (&s.arg_name, &s.arg_type)))
+                .map(|s| (&s.arg_name, s.as_value_type()))
+                .chain(
+                    self.param_args
+                        .iter()
+                        .map(|s| (&s.arg_name, s.arg_type.as_value_type())),
+                )
                 .map(|(s, t)| {
                     let _ident = Ident::new(s, Span::call_site());
-                    let t: TokenStream = t.as_value_type();
                     quote! {
                         pub #_ident: #t
                     }
@@ -98,10 +474,25 @@ impl ServiceSyntax {
             }
         };
         let result_ident = Ident::new("_result_group", Span::call_site());
+        let key_ident = Ident::new("__is_param_key", Span::call_site());
+        let keys_ident = Ident::new("__PARAM_KEYS", Span::call_site());
+        let key_matcher = {
+            let keys = self
+                .param_args
+                .iter()
+                .flat_map(ParamArg::keys)
+                .map(|(key, _)| Literal::string(&key));
+            quote! {
+                const #keys_ident: &[&str] = &[#(#keys),*];
+                #[allow(unused)]
+                let #key_ident = |_t: &str| #keys_ident.contains(&_t);
+            }
+        };
         let pos_args = self.positional_args.iter().map(|s| {
             let name_lit = Literal::string(&format!("{} [{}]", &s.arg_name, &s.arg_type));
             let unknown_note_lit = Literal::string(&format!("缺少位置参数 {}", &s.arg_name));
             let name_ident = Ident::new(&format!("__result_{}", &s.arg_name), Span::call_site());
+            let span_ident = Ident::new(&format!("__span_{}", &s.arg_name), Span::call_site());
             let cache_val = Ident::new("__cache_val", Span::call_site());
             let parser: TokenStream = s.arg_type.to_parser(ArgTypeParser {
                 val_ident: &cache_val,
@@ -109,7 +500,89 @@ impl ServiceSyntax {
                 name_lit: &name_lit,
             });
             let ret_type = s.arg_type.as_return_type();
+            let check = s.constraint.to_check(
+                &s.arg_type,
+                s.variadic,
+                &name_ident,
+                &result_ident,
+                &name_lit,
+                &quote! { #span_ident },
+            );
+            let missing = quote! {
+                #result_ident.push(crate::error::BotError::Syntax {
+                    found: param.peek().map(|_v| format!("{}", _v)),
+                    expected: Some(#name_lit.to_string()),
+                    note: Some(#unknown_note_lit.to_string()),
+                    span: param.peek().and_then(|_v| _v.span),
+                });
+            };
+            // the span of every item taken so far
+            let extend_span = quote! {
+                #span_ident = match (#span_ident, _span) {
+                    (Some(_a), Some(_b)) => Some(_a.to(_b)),
+                    (_a, _b) => _a.or(_b),
+                };
+            };
+            if s.greedy {
+                return quote! {
+                    let mut #span_ident: Option<crate::server::Span> = None;
+                    let #name_ident: Option<#ret_type> = {
+                        let mut _words: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                        while let Some(crate::server::Token {
+                            item: crate::server::ParamItem::Text(_t),
+                            span: _span,
+                        }) = param.peek()
+                        {
+                            if #key_ident(_t.as_str()) {
+                                break;
+                            }
+                            let _span = *_span;
+                            #extend_span
+                            _words.push(_t.to_owned());
+                            param.next();
+                        }
+                        if _words.is_empty() {
+                            #missing
+                            None
+                        } else {
+                            Some(_words.join(" "))
+                        }
+                    };
+                    #check
+                };
+            }
+            if s.variadic {
+                return quote! {
+                    let mut #span_ident: Option<crate::server::Span> = None;
+                    let #name_ident: Option<::std::vec::Vec<#ret_type>> = {
+                        let mut _items = ::std::vec::Vec::new();
+                        let mut _valid = true;
+                        while let Some(_item) = param.peek() {
+                            if matches!(&_item.item, crate::server::ParamItem::Text(_t) if #key_ident(_t.as_str())) {
+                                break;
+                            }
+                            let #cache_val = param.next().unwrap();
+                            let _span = #cache_val.span;
+                            #extend_span
+                            match #parser {
+                                Some(_x) => _items.push(_x),
+                                None => _valid = false,
+                            }
+                        }
+                        if !_valid {
+                            None
+                        } else if _items.is_empty() {
+                            #missing
+                            None
+                        } else {
+                            Some(_items)
+                        }
+                    };
+                    #check
+                };
+            }
             quote! {
+                let #span_ident = param.peek().and_then(|_v| _v.span);
                 let #name_ident: Option<#ret_type> = if let Some(#cache_val) = param.next() {
                     #parser
                 } else {
@@ -117,9 +590,11 @@ impl ServiceSyntax {
                         found: None,
                         expected: Some(#name_lit.to_string()),
                         note: Some(#unknown_note_lit.to_string()),
+                        span: None,
                     });
                     None
                 };
+                #check
             }
         });
         let param_args = {
@@ -135,11 +610,21 @@ impl ServiceSyntax {
                     name_lit: &name_lit,
                 });
                 let ret_type = s.arg_type.as_return_type();
-                if let Some(d) = &s.default {
+                let check = s.constraint.to_check(
+                    &s.arg_type,
+                    false,
+                    &name_ident,
+                    &result_ident,
+                    &name_lit,
+                    &quote! { #map_ident.get(#name_text).and_then(|_v| _v.span) },
+                );
+                let getter = if let Some(d) = &s.default {
                     let _token: TokenStream = d.to_value_token();
                     quote! {
                         let #name_ident: Option<#ret_type> = if let Some(_v) = #map_ident.get(#name_text) {
                             #parser
+                        } else if let Some(_v) = crate::command::param_default(#name_text).as_ref() {
+                            #parser
                         } else {
                             Some(#_token)
                         };
@@ -148,54 +633,139 @@ impl ServiceSyntax {
                     let unknown_note_lit =
                         Literal::string(&format!("缺少额外参数 {}", &s.arg_name));
                     quote! {
-                        let #name_ident: #ret_type = if let Some(_v) = #map_ident.get(#name_text) {
+                        let #name_ident: Option<#ret_type> = if let Some(_v) = #map_ident.get(#name_text) {
                             #parser
                         } else {
                             #result_ident.push(crate::error::BotError::Syntax {
                                 found: None,
                                 expected: Some(#name_lit.to_string()),
                                 note: Some(#unknown_note_lit.to_string()),
-                            })
+                                span: None,
+                            });
                             None
                         };
                     }
+                };
+                quote! {
+                    #getter
+                    #check
                 }
             });
             let matcher = self.param_args.iter().flat_map(|s| {
                 let _v = Literal::string(&s.arg_name);
-                s.alias
-                    .as_ref()
-                    .unwrap_or(&vec![])
-                    .iter()
-                    .chain([s.arg_name.to_owned()].iter())
-                    .map(|s| {
-                        let _s = Literal::string(s);
+                s.keys()
+                    .map(|(key, flag)| {
+                        let _s = Literal::string(&key);
+                        let flag = match flag {
+                            Some(on) => quote! { Some(#on) },
+                            None => quote! { None },
+                        };
                         quote! {
-                            #_s => #_v
+                            #_s => (#_v, #flag)
                         }
                     })
                     .collect::<Vec<_>>()
             });
+            let syntax_error = |found: TokenStream, expected: TokenStream, note: TokenStream| {
+                quote! {
+                    #result_ident.push(crate::error::BotError::Syntax {
+                        found: Some(#found),
+                        expected: #expected,
+                        note: Some(#note),
+                        span: _item.span,
+                    })
+                }
+            };
+            let unknown_error = syntax_error(
+                quote! { _text.to_owned() },
+                quote! { crate::utils::suggest::did_you_mean(&_text, #keys_ident).map(ToOwned::to_owned) },
+                quote! { format!("未知参数{}", crate::utils::suggest::hint(&_text, #keys_ident)) },
+            );
+            let duplicate_error = syntax_error(
+                quote! { _text.to_owned() },
+                quote! { Some(_key.to_string()) },
+                quote! { "重复的参数".to_string() },
+            );
+            let next_value = next_value(&self.param_args, quote! { _key }, quote! { &#key_ident });
+            let dangling_error = syntax_error(
+                quote! { _text.to_owned() },
+                quote! { Some(format!("{} 的取值", _key)) },
+                quote! { "参数缺少取值".to_string() },
+            );
+            let extra_error = syntax_error(
+                quote! { format!("{}", _item) },
+                quote! { None },
+                quote! { "多余的参数".to_string() },
+            );
             if self.param_args.is_empty() {
-                quote! {}
+                // nothing to match a key against, so anything left over is extra
+                quote! {
+                    for _item in param.by_ref() {
+                        #extra_error;
+                    }
+                }
             } else {
                 quote! {
-                    let mut #map_ident: ::std::collections::HashMap<::std::string::String, crate::server::ParamItem>
+                    #[allow(unused_mut)]
+                    let mut #map_ident: ::std::collections::HashMap<&str, crate::server::Token>
                         = ::std::collections::HashMap::new();
-                    while let Some(param_item) = param.next() {
-                        if let crate::server::ParamItem::Text(_text) = param_item {
-                            if let Some(_val) = param.peek() {
-                                #map_ident.insert(
-                                    match _text.as_str() {
-                                        #(#matcher),*,
-                                        _n @ _ => _n
-                                    }.to_string(),
-                                    (*_val).clone()
-                                );
-                                param.next();
-                            } else {
-                                break;
+                    while let Some(_item) = param.next() {
+                        let crate::server::ParamItem::Text(_text) = &_item.item else {
+                            #extra_error;
+                            continue;
+                        };
+                        let (_key, _flag): (&str, Option<bool>) = match _text.as_str() {
+                            #(#matcher,)*
+                            _ => {
+                                #unknown_error;
+                                // most likely the value of the unknown key
+                                if param.peek().is_some_and(|_v| {
+                                    !matches!(&_v.item, crate::server::ParamItem::Text(_t) if #key_ident(_t.as_str()))
+                                }) {
+                                    param.next();
+                                }
+                                continue;
+                            }
+                        };
+                        let _val = if let Some(_on) = _flag {
+                            // an explicit boolean after a flag is still accepted, but not after
+                            // its `no-` form, which would contradict itself
+                            let _explicit = param.next_if(|_v| {
+                                matches!(&_v.item, crate::server::ParamItem::Text(_t) if !#key_ident(_t.as_str())
+                                    && matches!(
+                                        _t.to_lowercase().as_str(),
+                                        "true" | "t" | "yes" | "y" | "false" | "f" | "no" | "n"
+                                    ))
+                            });
+                            match _explicit {
+                                Some(crate::server::Token {
+                                    item: crate::server::ParamItem::Text(_v),
+                                    span: _span,
+                                }) if !_on => {
+                                    #result_ident.push(crate::error::BotError::Syntax {
+                                        found: Some(format!("{} {}", _text, _v)),
+                                        expected: Some(_text.to_owned()),
+                                        note: Some("no- 开头的开关不能再带取值".to_string()),
+                                        span: _span,
+                                    });
+                                    continue;
+                                }
+                                Some(_v) => Some(_v),
+                                None => Some(crate::server::Token {
+                                    item: crate::server::ParamItem::Text(_on.to_string()),
+                                    span: _item.span,
+                                }),
                             }
+                        } else {
+                            #next_value
+                        };
+                        match _val {
+                            Some(_val) => {
+                                if #map_ident.insert(_key, _val).is_some() {
+                                    #duplicate_error;
+                                }
+                            }
+                            None => #dangling_error,
                         }
                     }
                     #(#getter)*
@@ -239,16 +809,23 @@ impl ServiceSyntax {
             }
         };
 
+        let follow_up = self.to_follow_up(&result_struct_type);
+
         quote! {
             #result_struct
 
+            #follow_up
+
             impl #result_struct_type {
-                fn parse<T>(mut param: ::std::iter::Peekable<T>)
+                fn parse<T, I>(param: ::std::iter::Peekable<T>)
                     -> crate::error::BotGroupResult<#result_struct_type>
                 where
-                    T: Iterator<Item = crate::server::ParamItem>
+                    T: Iterator<Item = I>,
+                    I: Into<crate::server::Token>,
                 {
+                    let mut param = param.map(Into::<crate::server::Token>::into).peekable();
                     let mut #result_ident = crate::error::BotErrorGroup::new();
+                    #key_matcher
                     #(#pos_args)*
                     #param_args
                     if #result_ident.as_ref().is_empty() {
@@ -266,10 +843,11 @@ impl ServiceSyntax {
                 #syntax_text_constant
                 type RESULT = #result_struct_type;
 
-                fn get_result<T>(param: ::std::iter::Peekable<T>)
+                fn get_result<T, I>(param: ::std::iter::Peekable<T>)
                     -> crate::error::BotGroupResult<#result_struct_type>
                 where
-                    T: Iterator<Item = crate::server::ParamItem>
+                    T: Iterator<Item = I>,
+                    I: Into<crate::server::Token>,
                 {
                     Self::parse(param)
                 }
@@ -302,6 +880,14 @@ pub struct ParamArg {
     pub arg_type: ArgType,
     pub default: Option<ArgValue>,
     pub description: String,
+    #[serde(flatten)]
+    pub constraint: Constraint,
+    /// Whether a quote-reply to the answer can change this argument.
+    #[serde(default)]
+    pub follow_up: bool,
+    /// The file this argument was declared in.
+    #[serde(skip)]
+    pub source: String,
 }
 
 //noinspection DuplicatedCode
@@ -309,9 +895,11 @@ impl Display for ParamArg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{name: <20}Type: {arg_type}.{optional}{default}\n                    {description}{alias}",
+            "{name: <20}Type: {arg_type}.{optional}{default}{constraint}{follow_up}\n                    {description}{alias}{negation}",
             name = &self.arg_name,
             arg_type = &self.arg_type,
+            constraint = &self.constraint,
+            follow_up = if self.follow_up { " Follow-up." } else { "" },
             optional = if self.default.is_some() {
                 " Optional."
             } else {
@@ -327,12 +915,35 @@ impl Display for ParamArg {
                 format!("\n                    Alias: {}", val.join(", "))
             } else {
                 "".to_string()
+            },
+            negation = if let ArgType::Flag = self.arg_type {
+                format!("\n                    Turn off: no-{}", self.arg_name)
+            } else {
+                "".to_string()
             }
         )
     }
 }
 
 impl ParamArg {
+    /// Every key that sets this argument, with the value it implies if it is a flag.
+    fn keys(&self) -> impl Iterator<Item = (String, Option<bool>)> + '_ {
+        let names = [&self.arg_name]
+            .into_iter()
+            .chain(self.alias.iter().flatten());
+        let flag = matches!(self.arg_type, ArgType::Flag);
+        names.flat_map(move |name| {
+            if flag {
+                vec![
+                    (name.clone(), Some(true)),
+                    (format!("no-{}", name), Some(false)),
+                ]
+            } else {
+                vec![(name.clone(), None)]
+            }
+        })
+    }
+
     fn to_token(&self) -> TokenStream {
         let default = if let Some(d) = &self.default {
             let d = d.to_token();
@@ -349,6 +960,8 @@ impl ParamArg {
         let lit_name = Literal::string(&self.arg_name);
         let arg_type = self.arg_type.to_token();
         let lit_desc = Literal::string(&self.description);
+        let constraint = self.constraint.to_token();
+        let follow_up = self.follow_up;
         quote! {
             crate::command::ParamArg {
                 arg_name: #lit_name,
@@ -356,6 +969,8 @@ impl ParamArg {
                 description: #lit_desc,
                 default: #default,
                 alias: #alias,
+                constraint: #constraint,
+                follow_up: #follow_up,
             }
         }
     }
@@ -366,31 +981,285 @@ pub struct PositionalArg {
     pub arg_name: String,
     pub arg_type: ArgType,
     pub description: String,
+    /// Join the words up to the first parameter key into one text.
+    #[serde(default)]
+    pub greedy: bool,
+    /// Collect every item up to the first parameter key.
+    #[serde(default)]
+    pub variadic: bool,
+    #[serde(flatten)]
+    pub constraint: Constraint,
+    /// The file this argument was declared in.
+    #[serde(skip)]
+    pub source: String,
 }
 
 //noinspection DuplicatedCode
+/// The value of the parameter named by `name` from `param`. The value of a `List` takes the
+/// values after it too, up to the next key as told by `is_key`, so that `levels 1 2 3` needs no
+/// quotes.
+fn next_value<'a>(
+    args: impl IntoIterator<Item = &'a ParamArg>,
+    name: TokenStream,
+    is_key: TokenStream,
+) -> TokenStream {
+    let lists = args
+        .into_iter()
+        .filter(|s| matches!(s.arg_type, ArgType::List(_)))
+        .map(|s| Literal::string(&s.arg_name))
+        .collect::<Vec<_>>();
+    if lists.is_empty() {
+        return quote! { param.next() };
+    }
+    quote! {
+        match #name {
+            #(#lists)|* => param
+                .next()
+                .map(|_v| crate::command::join_list(_v, param.by_ref(), #is_key)),
+            _ => param.next(),
+        }
+    }
+}
+
 impl Display for PositionalArg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{name: <20}Type: {arg_type}.\n                    {description}",
+            "{name: <20}Type: {arg_type}.{rest}{constraint}\n                    {description}",
             name = self.arg_name,
             arg_type = self.arg_type,
+            rest = if self.greedy {
+                " Greedy."
+            } else if self.variadic {
+                " Variadic."
+            } else {
+                ""
+            },
+            constraint = self.constraint,
             description = self.description,
         )
     }
 }
 
 impl PositionalArg {
+    /// How the argument shows up in the usage line.
+    fn tag(&self) -> String {
+        if self.greedy || self.variadic {
+            format!("[{}...]", self.arg_name)
+        } else {
+            format!("[{}]", self.arg_name)
+        }
+    }
+
+    fn as_value_type(&self) -> TokenStream {
+        let t = self.arg_type.as_value_type();
+        if self.variadic {
+            quote! { ::std::vec::Vec<#t> }
+        } else {
+            t
+        }
+    }
+
     fn to_token(&self) -> TokenStream {
         let lit_name = Literal::string(&self.arg_name);
         let arg_type = self.arg_type.to_token();
         let lit_desc = Literal::string(&self.description);
+        let constraint = self.constraint.to_token();
+        let greedy = self.greedy;
+        let variadic = self.variadic;
         quote! {
             crate::command::PositionalArg {
                 arg_name: #lit_name,
                 arg_type: #arg_type,
                 description: #lit_desc,
+                greedy: #greedy,
+                variadic: #variadic,
+                constraint: #constraint,
+            }
+        }
+    }
+}
+
+/// Value constraints of an argument, checked right after it is parsed.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Constraint {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub non_empty: bool,
+}
+
+//noinspection DuplicatedCode
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn bound(v: f64) -> String {
+            if v.fract() == 0.0 && v.abs() < 1e15 {
+                format!("{}", v as i64)
+            } else {
+                format!("{}", v)
+            }
+        }
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, " Range: [{}, {}].", bound(min), bound(max))?,
+            (Some(min), None) => write!(f, " Range: [{}, ...).", bound(min))?,
+            (None, Some(max)) => write!(f, " Range: (..., {}].", bound(max))?,
+            (None, None) => {}
+        }
+        if self.non_empty {
+            write!(f, " Non-empty.")?;
+        }
+        if let Some(len) = self.max_length {
+            write!(f, " Max length: {}.", len)?;
+        }
+        if let Some(regex) = &self.regex {
+            write!(f, " Pattern: {}.", regex)?;
+        }
+        Ok(())
+    }
+}
+
+impl Constraint {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.min.is_none()
+            && self.max.is_none()
+            && self.regex.is_none()
+            && self.max_length.is_none()
+            && !self.non_empty
+    }
+
+    /// Check that the constraints make sense for an argument of type `arg_type`.
+    fn validate(&self, arg_type: &ArgType) -> Vec<String> {
+        let mut errors = vec![];
+        if !arg_type.is_numeric() && (self.min.is_some() || self.max.is_some()) {
+            errors.push(format!("`min`/`max` are not applicable to {}", arg_type));
+        }
+        if !arg_type.is_textual()
+            && (self.regex.is_some() || self.max_length.is_some() || self.non_empty)
+        {
+            errors.push(format!(
+                "`regex`/`max_length`/`non_empty` are not applicable to {}",
+                arg_type
+            ));
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                errors.push(format!("`min` ({}) is greater than `max` ({})", min, max));
+            }
+        }
+        if let Some(Err(e)) = self.regex.as_deref().map(regex::Regex::new) {
+            errors.push(format!("invalid `regex`: {}", e));
+        }
+        errors
+    }
+
+    /// Same rules as the runtime check, so that a default can never be rejected.
+    fn check_default(&self, value: &ArgValue) -> Result<(), String> {
+        let out_of_range =
+            |v: f64| self.min.is_some_and(|min| v < min) || self.max.is_some_and(|max| v > max);
+        let violated = match value {
+            ArgValue::Int(v) => out_of_range(*v as f64),
+            ArgValue::Float(v) | ArgValue::Isk(v) => out_of_range(*v),
+            ArgValue::AnyText(v) => {
+                (self.non_empty && v.trim().is_empty())
+                    || self.max_length.is_some_and(|len| v.chars().count() > len)
+                    || self
+                        .regex
+                        .as_deref()
+                        .and_then(|r| regex::Regex::new(r).ok())
+                        .is_some_and(|r| !r.is_match(v))
+            }
+            _ => false,
+        };
+        if violated {
+            Err(format!("default `{}` does not satisfy{}", value, self))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn to_token(&self) -> TokenStream {
+        fn option(v: Option<impl ToTokens>) -> TokenStream {
+            match v {
+                Some(v) => quote! { Some(#v) },
+                None => quote! { None },
+            }
+        }
+        let min = option(self.min.map(Literal::f64_suffixed));
+        let max = option(self.max.map(Literal::f64_suffixed));
+        let regex = option(self.regex.as_deref().map(Literal::string));
+        let max_length = option(self.max_length.map(Literal::usize_suffixed));
+        let non_empty = self.non_empty;
+        quote! {
+            crate::command::Constraint {
+                min: #min,
+                max: #max,
+                regex: #regex,
+                max_length: #max_length,
+                non_empty: #non_empty,
+            }
+        }
+    }
+
+    /// Drop the parsed value in `name_ident` and report it if it violates the constraints.
+    fn to_check(
+        &self,
+        arg_type: &ArgType,
+        variadic: bool,
+        name_ident: &Ident,
+        result_ident: &Ident,
+        name_lit: &Literal,
+        span: &TokenStream,
+    ) -> TokenStream {
+        if self.is_empty() {
+            return quote! {};
+        }
+        let constraint = self.to_token();
+        // the compiled regex is kept for the next message
+        let (regex, check) = if arg_type.is_numeric() {
+            (quote! {}, quote! { check_number(*_v as f64) })
+        } else {
+            (
+                quote! {
+                    static _REGEX: ::std::sync::OnceLock<::regex::Regex> =
+                        ::std::sync::OnceLock::new();
+                },
+                quote! { check_text(_v, &_REGEX) },
+            )
+        };
+        let check = quote! {
+            |_v| match (#constraint).#check {
+                Ok(()) => true,
+                Err(_note) => {
+                    #result_ident.push(crate::error::BotError::Syntax {
+                        found: Some(_v.to_string()),
+                        expected: Some(#name_lit.to_string()),
+                        note: Some(_note),
+                        span: #span,
+                    });
+                    false
+                }
+            }
+        };
+        if variadic {
+            quote! {
+                let #name_ident = {
+                    #regex
+                    #name_ident.filter(|_vs| _vs.iter().all(#check))
+                };
+            }
+        } else {
+            quote! {
+                let #name_ident = {
+                    #regex
+                    #name_ident.filter(#check)
+                };
             }
         }
     }
@@ -403,6 +1272,11 @@ pub enum ArgValue {
     Float(f64),
     Int(i64),
     Boolean(bool),
+    Isk(f64),
+    /// In seconds.
+    Duration(u64),
+    List(Vec<ArgValue>),
+    Server(String),
 }
 
 //noinspection DuplicatedCode
@@ -414,18 +1288,96 @@ impl Display for ArgValue {
             Self::Float(val) => write!(f, "{:?}", val),
             Self::Int(val) => write!(f, "{:?}", val),
             Self::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Self::Isk(val) => write!(f, "{}", format_isk(*val)),
+            Self::Duration(val) => write!(f, "{}", format_duration(*val)),
+            Self::List(val) => write!(f, "{}", val.iter().join(",")),
+            Self::Server(val) => match server_variant(val) {
+                Some((_, name)) => write!(f, "{}", name),
+                None => write!(f, "{}", val),
+            },
+        }
+    }
+}
+
+//noinspection DuplicatedCode
+fn format_isk(value: f64) -> String {
+    let (value, unit) = match value.abs() {
+        v if v >= 1e9 => (value / 1e9, "b"),
+        v if v >= 1e6 => (value / 1e6, "m"),
+        v if v >= 1e3 => (value / 1e3, "k"),
+        _ => (value, ""),
+    };
+    format!("{}{}", (value * 100.0).round() / 100.0, unit)
+}
+
+//noinspection DuplicatedCode
+fn format_duration(mut seconds: u64) -> String {
+    if seconds == 0 {
+        return "0s".into();
+    }
+    let mut text = String::new();
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if seconds >= size {
+            text += &format!("{}{}", seconds / size, unit);
+            seconds %= size;
         }
     }
+    text
+}
+
+/// The `Server` variant and its short name, mirroring `Server::parse_from`.
+fn server_variant(name: &str) -> Option<(&'static str, &'static str)> {
+    match name.to_ascii_lowercase().as_str() {
+        "tq" | "trans" | "tranquility" => Some(("Tranquility", "tq")),
+        "se" | "seren" | "serenity" => Some(("Serenity", "se")),
+        _ => None,
+    }
 }
 
 impl ArgValue {
+    /// Check that this value can be the default of an argument of type `arg_type`.
+    fn check_type(&self, arg_type: &ArgType) -> Result<(), String> {
+        match (self, arg_type) {
+            (Self::AnyText(_), ArgType::AnyText)
+            | (Self::Float(_), ArgType::Float)
+            | (Self::Int(_), ArgType::Int)
+            | (Self::Boolean(_), ArgType::Boolean | ArgType::Flag)
+            | (Self::Isk(_), ArgType::Isk)
+            | (Self::Duration(_), ArgType::Duration) => Ok(()),
+            (Self::EnumText(v), ArgType::EnumText(variants)) => {
+                if variants.contains(v) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "default `{}` is not one of [{}]",
+                        v,
+                        variants.join(", ")
+                    ))
+                }
+            }
+            (Self::Server(v), ArgType::Server) => server_variant(v)
+                .map(|_| ())
+                .ok_or_else(|| format!("default `{}` is not a server", v)),
+            (Self::List(v), ArgType::List(t)) => v.iter().try_for_each(|v| v.check_type(t)),
+            (_, ArgType::Mention) => Err("MENTION cannot have a default".into()),
+            _ => Err(format!(
+                "default value `{}` does not match arg_type `{}`",
+                self, arg_type
+            )),
+        }
+    }
+
     pub fn to_value_token(&self) -> TokenStream {
         match self {
-            Self::AnyText(t) | Self::EnumText(t) => {
+            Self::AnyText(t) => {
+                let t = Literal::string(t);
+                quote! { #t.to_string() }
+            }
+            Self::EnumText(t) => {
                 let t = Literal::string(t);
                 quote! { #t }
             }
-            Self::Float(t) => {
+            Self::Float(t) | Self::Isk(t) => {
                 let t = Literal::f64_suffixed(*t);
                 quote! { #t }
             }
@@ -440,6 +1392,18 @@ impl ArgValue {
                     quote! { false }
                 }
             }
+            Self::Duration(t) => {
+                let t = Literal::u64_suffixed(*t);
+                quote! { ::std::time::Duration::from_secs(#t) }
+            }
+            Self::List(t) => {
+                let t = t.iter().map(ArgValue::to_value_token);
+                quote! { ::std::vec![#(#t),*] }
+            }
+            Self::Server(t) => {
+                let variant = Ident::new(server_variant(t).unwrap().0, Span::call_site());
+                quote! { crate::constant::eve::server::Server::#variant }
+            }
         }
     }
 
@@ -468,6 +1432,22 @@ impl ArgValue {
                     quote! { crate::command::ArgValue::Boolean(false) }
                 }
             }
+            Self::Isk(v) => {
+                let lit_val = Literal::f64_suffixed(*v);
+                quote! { crate::command::ArgValue::Isk(#lit_val) }
+            }
+            Self::Duration(_) | Self::Server(_) => {
+                let val = self.to_value_token();
+                let variant = match self {
+                    Self::Duration(_) => quote! { Duration },
+                    _ => quote! { Server },
+                };
+                quote! { crate::command::ArgValue::#variant(#val) }
+            }
+            Self::List(v) => {
+                let v = v.iter().map(ArgValue::to_token);
+                quote! { crate::command::ArgValue::List(&[#(#v),*]) }
+            }
         }
     }
 }
@@ -479,6 +1459,13 @@ pub enum ArgType {
     Float,
     Int,
     Boolean,
+    Isk,
+    Duration,
+    List(Box<ArgType>),
+    Mention,
+    Server,
+    /// A key without a value, `no-<key>` turns it off.
+    Flag,
 }
 
 //noinspection DuplicatedCode
@@ -490,6 +1477,12 @@ impl Display for ArgType {
             Self::Float => write!(f, "FLOAT"),
             Self::Int => write!(f, "INT"),
             Self::Boolean => write!(f, "BOOLEAN"),
+            Self::Isk => write!(f, "ISK"),
+            Self::Duration => write!(f, "DURATION"),
+            Self::List(t) => write!(f, "LIST<{}>", t),
+            Self::Mention => write!(f, "MENTION"),
+            Self::Server => write!(f, "SERVER"),
+            Self::Flag => write!(f, "FLAG"),
         }
     }
 }
@@ -501,34 +1494,41 @@ struct ArgTypeParser<'a> {
 }
 
 impl ArgType {
-    fn as_return_type(&self) -> TokenStream {
+    fn validate(&self) -> Result<(), String> {
         match self {
-            ArgType::AnyText => quote! { ::std::string::String },
-            ArgType::EnumText(_) => quote! { &'static str },
-            ArgType::Float => quote! { f64 },
-            ArgType::Int => quote! { i64 },
-            ArgType::Boolean => quote! { bool },
+            Self::EnumText(v) if v.is_empty() => Err("EnumText must not be empty".into()),
+            Self::List(t) => match t.as_ref() {
+                Self::List(_) | Self::Mention | Self::Flag => {
+                    Err(format!("{} cannot be a List element", t))
+                }
+                t => t.validate(),
+            },
+            _ => Ok(()),
         }
     }
-    fn to_parser(&self, val: ArgTypeParser<'_>) -> TokenStream {
-        let val_ident = val.val_ident;
-        let result_ident = val.result_ident;
-        let name_lit = val.name_lit;
+
+    /// Types accepting `min` and `max`.
+    #[inline]
+    fn is_numeric(&self) -> bool {
+        matches!(self, Self::Int | Self::Float | Self::Isk)
+    }
+
+    /// Types accepting `regex`, `max_length` and `non_empty`.
+    #[inline]
+    fn is_textual(&self) -> bool {
+        matches!(self, Self::AnyText)
+    }
+
+    #[inline]
+    fn as_return_type(&self) -> TokenStream {
+        self.as_value_type()
+    }
+
+    /// An `Option` of the value parsed from `text`, which is a `&str`.
+    fn text_parser(&self, text: TokenStream) -> TokenStream {
         match self {
-            ArgType::AnyText => quote! {
-                if let crate::server::ParamItem::Text(t) = &#val_ident {
-                    Some(t.to_owned())
-                } else {
-                    #result_ident.push(crate::error::BotError::Syntax {
-                        found: Some(format!("{:?}", #val_ident)),
-                        expected: Some(#name_lit.to_string()),
-                        note: Some("参数类型错误".to_string()),
-                    });
-                    None
-                }
-            },
-            ArgType::EnumText(enums) => {
-                assert!(!enums.is_empty(), "enum text is empty");
+            Self::AnyText => quote! { Some(#text.to_owned()) },
+            Self::EnumText(enums) => {
                 let pattern = enums.iter().map(|s| {
                     let _lit = Literal::string(s);
                     quote! {
@@ -536,82 +1536,83 @@ impl ArgType {
                     }
                 });
                 quote! {
-                    if let crate::server::ParamItem::Text(t) = &#val_ident {
-                        match t.as_str() {
-                            #(#pattern)*
-                            _ => {
-                                #result_ident.push(crate::error::BotError::Syntax {
-                                    found: Some(format!("{:?}", #val_ident)),
-                                    expected: Some(#name_lit.to_string()),
-                                    note: Some("枚举参数非法值".to_string()),
-                                });
-                                None
-                            }
-                        }
-                    } else {
-                        #result_ident.push(crate::error::BotError::Syntax {
-                            found: Some(format!("{:?}", #val_ident)),
-                            expected: Some(#name_lit.to_string()),
-                            note: Some("参数类型错误".to_string()),
-                        });
-                        None
+                    match #text {
+                        #(#pattern)*
+                        _ => None,
                     }
                 }
             }
-            ArgType::Float => quote! {
-                match &#val_ident {
-                    crate::server::ParamItem::Text(_t) => if let Ok(_f) = _t.parse::<f64>() {
-                        Some(_f)
-                    } else {
-                        #result_ident.push(crate::error::BotError::Syntax {
-                            found: Some(format!("{}", #val_ident)),
-                            expected: Some(#name_lit.to_string()),
-                            note: Some("无法解析入参".to_string()),
-                        });
-                        None
-                    },
-                    crate::server::ParamItem::At(_at) => Some(_at.to_owned() as f64),
+            Self::Float => quote! { #text.parse::<f64>().ok() },
+            Self::Int => quote! { #text.parse::<i64>().ok() },
+            Self::Boolean | Self::Flag => quote! {
+                match #text.to_lowercase().as_str() {
+                    "true" | "t" | "yes" | "y" => Some(true),
+                    "false" | "f" | "no" | "n" => Some(false),
+                    _ => None,
                 }
             },
-            ArgType::Int => quote! {
-                match &#val_ident {
-                    crate::server::ParamItem::Text(_t) => if let Ok(_f) = _t.parse::<i64>() {
-                        Some(_f)
-                    } else {
-                        #result_ident.push(crate::error::BotError::Syntax {
-                            found: Some(format!("{}", #val_ident)),
-                            expected: Some(#name_lit.to_string()),
-                            note: Some("无法解析入参".to_string()),
-                        });
-                        None
-                    },
-                    crate::server::ParamItem::At(_at) => Some(_at.to_owned() as i64),
+            Self::Isk => quote! { crate::command::parse_isk(#text) },
+            Self::Duration => quote! { crate::command::parse_duration(#text) },
+            Self::List(t) => {
+                let element = t.text_parser(quote! { _s });
+                quote! {
+                    #text
+                        .split(|_c: char| _c == ',' || _c == '，' || _c.is_whitespace())
+                        .filter(|_s| !_s.is_empty())
+                        .map(|_s| #element)
+                        .collect::<Option<::std::vec::Vec<_>>>()
                 }
-            },
-            ArgType::Boolean => quote! {
-                match #val_ident {
-                    crate::server::ParamItem::Text(_t) => match _t.to_lowercase().as_str() {
-                        "true" | "t" | "yes" | "y" => Some(true),
-                        "false" | "f" | "no" | "n" => Some(false),
-                        _ => {
-                        #result_ident.push(crate::error::BotError::Syntax {
-                            found: Some(format!("{}", #val_ident)),
-                            expected: Some(#name_lit.to_string()),
-                            note: Some("无法解析入参".to_string()),
-                        });
-                        None
-                    }
-                    },
-                    _ => {
-                        #result_ident.push(crate::error::BotError::Syntax {
-                            found: Some(format!("{}", #val_ident)),
-                            expected: Some(#name_lit.to_string()),
-                            note: Some("无法解析入参".to_string()),
-                        });
-                        None
-                    }
+            }
+            // rejected by `validate`
+            Self::Mention => quote! { None::<u64> },
+            Self::Server => {
+                quote! { crate::constant::eve::server::Server::parse_from(#text).ok() }
+            }
+        }
+    }
+
+    fn to_parser(&self, val: ArgTypeParser<'_>) -> TokenStream {
+        let val_ident = val.val_ident;
+        let result_ident = val.result_ident;
+        let name_lit = val.name_lit;
+        let error = |note: &str| {
+            let note = Literal::string(note);
+            quote! {
+                {
+                    #result_ident.push(crate::error::BotError::Syntax {
+                        found: Some(format!("{}", #val_ident)),
+                        expected: Some(#name_lit.to_string()),
+                        note: Some(#note.to_string()),
+                        span: #val_ident.span,
+                    });
+                    None
                 }
-            },
+            }
+        };
+        if let Self::Mention = self {
+            let type_error = error("需要 @ 一名成员");
+            return quote! {
+                match &#val_ident.item {
+                    crate::server::ParamItem::At(_at) => Some(*_at),
+                    _ => #type_error,
+                }
+            };
+        }
+        let parser = self.text_parser(quote! { _t.as_str() });
+        let parse_error = error(match self {
+            Self::EnumText(_) => "枚举参数非法值",
+            Self::Server => "不合法的服务器类型",
+            _ => "无法解析入参",
+        });
+        let type_error = error("参数类型错误");
+        quote! {
+            match &#val_ident.item {
+                crate::server::ParamItem::Text(_t) => match #parser {
+                    Some(_x) => Some(_x),
+                    None => #parse_error,
+                },
+                _ => #type_error,
+            }
         }
     }
 
@@ -619,9 +1620,16 @@ impl ArgType {
         match self {
             Self::AnyText => quote! { ::std::string::String },
             Self::EnumText(_) => quote! { &'static str },
-            Self::Float => quote! { f64 },
+            Self::Float | Self::Isk => quote! { f64 },
             Self::Int => quote! { i64 },
-            Self::Boolean => quote! { bool },
+            Self::Boolean | Self::Flag => quote! { bool },
+            Self::Duration => quote! { ::std::time::Duration },
+            Self::List(t) => {
+                let t = t.as_value_type();
+                quote! { ::std::vec::Vec<#t> }
+            }
+            Self::Mention => quote! { u64 },
+            Self::Server => quote! { crate::constant::eve::server::Server },
         }
     }
 
@@ -632,45 +1640,125 @@ impl ArgType {
             Self::Float => quote! { crate::command::ArgType::Float },
             Self::Int => quote! { crate::command::ArgType::Int },
             Self::Boolean => quote! { crate::command::ArgType::Boolean },
+            Self::Isk => quote! { crate::command::ArgType::Isk },
+            Self::Duration => quote! { crate::command::ArgType::Duration },
+            Self::List(t) => {
+                let t = t.to_token();
+                quote! { crate::command::ArgType::List(&#t) }
+            }
+            Self::Mention => quote! { crate::command::ArgType::Mention },
+            Self::Server => quote! { crate::command::ArgType::Server },
+            Self::Flag => quote! { crate::command::ArgType::Flag },
         }
     }
 }
 
-fn print_fs_err<T: Default>(fp: impl AsRef<std::path::Path>) -> Box<dyn Fn(std::io::Error) -> T> {
-    let full_path = if fp.as_ref().is_absolute() {
-        Ok(fp.as_ref().to_owned())
-    } else {
-        fp.as_ref().canonicalize()
-    };
-    let to_find = std::env::current_dir().map(|val| val.join(fp.as_ref()));
-    match full_path {
-        Ok(val) => Box::new(move |e| {
-            panic!(
-                "\nerror: {:?}\nfile path: {:?}\nto find: {:?}",
-                e, val, to_find
-            )
-        }),
-        Err(err) => Box::new(move |e| {
-            panic!(
-                "\nerror: {:?}\nfile path error: {:?}\nto find: {:?}",
-                e, err, to_find
-            )
-        }),
-    }
-}
-
 pub fn arg_producing(attr: RawTokenStream, input: RawTokenStream) -> RawTokenStream {
     let lit_fp = syn::parse_macro_input!(attr as syn::LitStr);
     let s: ItemStruct = syn::parse_macro_input!(input as syn::ItemStruct);
     let struct_ident = s.ident.clone();
-    let fp = lit_fp.value();
-    let data: ServiceSyntax =
-        serde_json::from_slice(&std::fs::read(&fp).unwrap_or_else(print_fs_err(&fp))).unwrap();
-    let data = data.process_mixin();
+    let mut files = vec![];
+    let data = ServiceSyntax::load(lit_fp.value(), &lit_fp, &mut files)
+        .and_then(|data| data.validate(&lit_fp).map(|_| data));
+    let data = match data {
+        Ok(data) => data,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let tracker = crate::path::track(&files);
     // std::fs::write(
     //     format!("./macro-expand/test.output.{}.rs", s.ident),
     //     format!("{}", &data.to_parser(&struct_ident)),
     // )
     // .unwrap();
-    RawTokenStream::from(data.to_parser(&struct_ident))
+    let parser = data.to_parser(&struct_ident);
+    RawTokenStream::from(quote! {
+        #tracker
+        #parser
+    })
+}
+
+#[test]
+fn test_validate() {
+    let lit = LitStr::new("test.json", Span::call_site());
+    let mut data: ServiceSyntax = serde_json::from_str(
+        r#"{
+            "title": "Test",
+            "description": "Test",
+            "arg_prefix": "eve test",
+            "positional_args": [
+                {
+                    "arg_name": "type_name",
+                    "arg_type": "AnyText",
+                    "greedy": true,
+                    "description": ""
+                },
+                {
+                    "arg_name": "count",
+                    "arg_type": "Int",
+                    "greedy": true,
+                    "variadic": true,
+                    "description": ""
+                }
+            ],
+            "param_args": [
+                {
+                    "arg_name": "server",
+                    "alias": ["type_name"],
+                    "description": "",
+                    "arg_type": { "EnumText": ["se", "tq"] },
+                    "default": { "EnumText": "cn" }
+                },
+                {
+                    "arg_name": "level",
+                    "description": "",
+                    "arg_type": "Int",
+                    "default": { "Float": 0.0 }
+                },
+                { "arg_name": "empty", "description": "", "arg_type": { "EnumText": [] } },
+                {
+                    "arg_name": "mat",
+                    "description": "",
+                    "arg_type": "Int",
+                    "min": 0,
+                    "max": 10,
+                    "default": { "Int": 11 }
+                },
+                { "arg_name": "bound", "description": "", "arg_type": "Float", "min": 5, "max": 1 },
+                { "arg_name": "flag", "description": "", "arg_type": "Boolean", "regex": "^a$" }
+            ]
+        }"#,
+    )
+    .unwrap();
+    data.positional_args
+        .iter_mut()
+        .for_each(|s| s.source = "test.json".into());
+    data.param_args
+        .iter_mut()
+        .for_each(|s| s.source = "test.json".into());
+    let errors = data
+        .validate(&lit)
+        .unwrap_err()
+        .into_iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            "`test.json`: `param_args.server.alias[0]`: `type_name` is already declared at \
+             `test.json`: `positional_args.type_name.arg_name`",
+            "`test.json`: `param_args.server.default`: default `cn` is not one of [se, tq]",
+            "`test.json`: `param_args.level.default`: default value `0.0` does not match \
+             arg_type `INT`",
+            "`test.json`: `param_args.empty.arg_type`: EnumText must not be empty",
+            "`test.json`: `param_args.mat.default`: default `11` does not satisfy \
+             Range: [0, 10].",
+            "`test.json`: `param_args.bound`: `min` (5) is greater than `max` (1)",
+            "`test.json`: `param_args.flag`: `regex`/`max_length`/`non_empty` are not \
+             applicable to BOOLEAN",
+            "`test.json`: `positional_args.type_name`: only the last positional argument can \
+             be greedy or variadic",
+            "`test.json`: `positional_args.count`: `greedy` and `variadic` are exclusive",
+            "`test.json`: `positional_args.count`: `greedy` is not applicable to INT",
+        ]
+    );
 }